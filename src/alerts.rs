@@ -0,0 +1,499 @@
+// The web build has no parser thread to check for alerts, it only needs the rules for the config
+#![cfg_attr(target_arch = "wasm32", allow(dead_code))]
+
+use std::collections::HashSet;
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs::OpenOptions;
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+#[cfg(not(target_arch = "wasm32"))]
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::chat_log::ParsedChatLog;
+use crate::{ChatType, Message};
+
+/// A rule from the config: a condition on new messages, and what to do when one matches.
+/// Everything given in the condition has to match, and a rule with no condition never does.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Default)]
+#[serde(default)]
+pub struct AlertRule {
+    pub name: String,
+    pub channel: Option<ChatType>,
+    pub sender: Option<String>,
+    /// Case insensitive, anywhere in the message
+    pub text: Option<String>,
+    /// Case insensitive, anywhere in the message
+    pub regex: Option<String>,
+
+    pub highlight: bool,
+    pub flash_title: bool,
+    pub desktop_notification: bool,
+    pub sound: bool,
+    pub append_to_file: Option<PathBuf>,
+}
+
+impl AlertRule {
+    pub fn actions(&self) -> Vec<AlertAction> {
+        let mut actions = vec![];
+        if self.highlight {
+            actions.push(AlertAction::Highlight);
+        }
+        if self.flash_title {
+            actions.push(AlertAction::FlashTitle);
+        }
+        if self.desktop_notification {
+            actions.push(AlertAction::DesktopNotification);
+        }
+        if self.sound {
+            actions.push(AlertAction::Sound);
+        }
+        if let Some(path) = &self.append_to_file {
+            actions.push(AlertAction::AppendToFile(path.clone()));
+        }
+        return actions;
+    }
+
+    fn has_condition(&self) -> bool {
+        return self.channel.is_some()
+            || self.sender.is_some()
+            || self.text.is_some()
+            || self.regex.is_some();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlertAction {
+    Highlight,
+    FlashTitle,
+    DesktopNotification,
+    Sound,
+    AppendToFile(PathBuf),
+}
+
+/// One action to take for a message that matched a rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub rule: String,
+    pub message: Message,
+    pub action: AlertAction,
+}
+
+/// Somewhere alerts go. Every sink gets every alert, and ignores the actions it doesn't handle.
+pub trait AlertSink {
+    fn send(&mut self, alert: &Alert) -> Result<(), String>;
+}
+
+/// Checks new messages against the alert rules.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    /// Same order as rules, None where the regex didn't compile
+    regexes: Vec<Option<Regex>>,
+    /// Newest message checked. Nothing's been checked until the first call to check.
+    last_checked: Option<Option<u32>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl AlertEngine {
+    pub fn new() -> Self {
+        return AlertEngine {
+            rules: vec![],
+            regexes: vec![],
+            last_checked: None,
+        };
+    }
+
+    /// Forgets what's been checked, for when a different chat log is opened. Its ids have nothing
+    /// to do with the last one's, so everything in it is history until the next check.
+    pub fn reset(&mut self) {
+        self.last_checked = None;
+    }
+
+    /// Sends alerts for messages that have arrived since the last check. Messages already there
+    /// on the first check are history, so don't alert.
+    pub fn check(
+        &mut self,
+        parsed: &ParsedChatLog,
+        rules: &[AlertRule],
+        sinks: &mut [&mut dyn AlertSink],
+    ) {
+        if self.rules != rules {
+            self.set_rules(rules);
+        }
+
+        let newest = parsed.newest_message_id(ChatType::All);
+        let last_checked = match self.last_checked {
+            Some(last_checked) => last_checked,
+            None => {
+                self.last_checked = Some(newest);
+                return;
+            }
+        };
+
+        for message in parsed.messages_after(last_checked) {
+            for (rule, regex) in self.rules.iter().zip(&self.regexes) {
+                if !rule_matches(rule, regex.as_ref(), message) {
+                    continue;
+                }
+                for action in rule.actions() {
+                    let alert = Alert {
+                        rule: rule.name.clone(),
+                        message: message.clone(),
+                        action,
+                    };
+                    for sink in sinks.iter_mut() {
+                        if let Err(e) = sink.send(&alert) {
+                            eprintln!("Alert \"{}\" failed: {}", rule.name, e);
+                        }
+                    }
+                }
+            }
+        }
+        self.last_checked = Some(newest);
+    }
+
+    fn set_rules(&mut self, rules: &[AlertRule]) {
+        self.rules = rules.to_vec();
+        self.regexes = rules
+            .iter()
+            .map(|rule| {
+                let pattern = rule.regex.as_ref()?;
+                match RegexBuilder::new(pattern).case_insensitive(true).build() {
+                    Ok(regex) => Some(regex),
+                    Err(e) => {
+                        eprintln!("Alert \"{}\" has a bad regex: {}", rule.name, e);
+                        None
+                    }
+                }
+            })
+            .collect();
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn rule_matches(rule: &AlertRule, regex: Option<&Regex>, message: &Message) -> bool {
+    if !rule.has_condition() {
+        return false;
+    }
+    if let Some(channel) = rule.channel {
+        if channel != ChatType::All && channel != message.chat_type {
+            return false;
+        }
+    }
+    if let Some(sender) = &rule.sender {
+        if !sender.eq_ignore_ascii_case(&message.sender) {
+            return false;
+        }
+    }
+    if let Some(text) = &rule.text {
+        if !message
+            .contents
+            .to_lowercase()
+            .contains(&text.to_lowercase())
+        {
+            return false;
+        }
+    }
+    if rule.regex.is_some() {
+        // A regex that didn't compile can't match anything
+        if !regex.is_some_and(|regex| regex.is_match(&message.contents)) {
+            return false;
+        }
+    }
+    return true;
+}
+
+/// Desktop notifications, sounds and alert files.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct SystemAlertSink;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl AlertSink for SystemAlertSink {
+    fn send(&mut self, alert: &Alert) -> Result<(), String> {
+        match &alert.action {
+            AlertAction::DesktopNotification => {
+                notify_rust::Notification::new()
+                    .appname("Puzzle Pirates Chat Tracker")
+                    .summary(&alert.rule)
+                    .body(&alert.message.contents)
+                    .show()
+                    .map_err(|e| format!("Couldn't show notification: {}", e))?;
+            }
+            AlertAction::Sound => play_alert_sound()?,
+            AlertAction::AppendToFile(path) => {
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| format!("Couldn't open {}: {}", path.to_string_lossy(), e))?;
+                let date = match alert.message.date {
+                    Some(date) => date.to_string() + " ",
+                    None => String::new(),
+                };
+                writeln!(file, "{}{}", date, alert.message.contents)
+                    .map_err(|e| format!("Couldn't write to {}: {}", path.to_string_lossy(), e))?;
+            }
+            AlertAction::Highlight | AlertAction::FlashTitle => {}
+        }
+        return Ok(());
+    }
+}
+
+/// Plays the system's own alert sound with whatever player the platform comes with, so there's
+/// no audio library to build.
+#[cfg(not(target_arch = "wasm32"))]
+fn play_alert_sound() -> Result<(), String> {
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        let mut command = std::process::Command::new("powershell");
+        command
+            .args([
+                "-NoProfile",
+                "-Command",
+                "[System.Media.SystemSounds]::Exclamation.Play(); Start-Sleep -Milliseconds 500",
+            ])
+            .creation_flags(CREATE_NO_WINDOW);
+        command
+    };
+    #[cfg(target_os = "macos")]
+    let mut command = {
+        let mut command = std::process::Command::new("afplay");
+        command.arg("/System/Library/Sounds/Ping.aiff");
+        command
+    };
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let mut command = {
+        let mut command = std::process::Command::new("canberra-gtk-play");
+        command.args(["--id", "message-new-instant"]);
+        command
+    };
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("Couldn't play alert sound: {}", e))?;
+    // Don't hold up the parser while the sound plays, but don't leave the process hanging around
+    std::thread::spawn(move || child.wait());
+    return Ok(());
+}
+
+/// Alerts for the window to show: messages to highlight, and whether to flash the title.
+/// Clones share the same alerts, so one can be handed to the parser thread.
+#[derive(Clone, Default)]
+pub struct WindowAlerts {
+    state: Arc<Mutex<WindowAlertState>>,
+}
+
+#[derive(Default)]
+struct WindowAlertState {
+    highlighted: HashSet<u32>,
+    flash_title: bool,
+}
+
+impl WindowAlerts {
+    pub fn is_highlighted(&self, message_id: u32) -> bool {
+        return self.state.lock().unwrap().highlighted.contains(&message_id);
+    }
+
+    /// Forgets every alert, for when a different chat log is opened and the ids mean other messages.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn clear(&self) {
+        *self.state.lock().unwrap() = WindowAlertState::default();
+    }
+
+    /// Whether the title should start flashing. Only true once per alert.
+    pub fn take_flash_title(&self) -> bool {
+        return std::mem::take(&mut self.state.lock().unwrap().flash_title);
+    }
+}
+
+impl AlertSink for WindowAlerts {
+    fn send(&mut self, alert: &Alert) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        match alert.action {
+            AlertAction::Highlight => {
+                state.highlighted.insert(alert.message.id);
+            }
+            AlertAction::FlashTitle => state.flash_title = true,
+            _ => {}
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use super::{
+        Alert, AlertAction, AlertEngine, AlertRule, AlertSink, SystemAlertSink, WindowAlerts,
+    };
+    use crate::chat_log::ParsedChatLog;
+    use crate::ChatType;
+
+    /// Keeps every alert it's sent, instead of bothering a real desktop.
+    #[derive(Default)]
+    struct RecordingSink {
+        alerts: Vec<Alert>,
+    }
+
+    impl AlertSink for RecordingSink {
+        fn send(&mut self, alert: &Alert) -> Result<(), String> {
+            self.alerts.push(alert.clone());
+            return Ok(());
+        }
+    }
+
+    #[test]
+    fn test_alerts_for_new_messages() {
+        let rules = vec![
+            AlertRule {
+                name: "Weavery".to_string(),
+                channel: Some(ChatType::Trade),
+                text: Some("WEAVERY".to_string()),
+                highlight: true,
+                sound: true,
+                ..Default::default()
+            },
+            AlertRule {
+                name: "Tells from Bob".to_string(),
+                sender: Some("bob".to_string()),
+                regex: Some(r"\bspades?\b".to_string()),
+                desktop_notification: true,
+                ..Default::default()
+            },
+            AlertRule {
+                name: "No condition".to_string(),
+                flash_title: true,
+                ..Default::default()
+            },
+        ];
+        let mut engine = AlertEngine::new();
+        let mut sink = RecordingSink::default();
+        let mut log = "[16:05:04] Barry trade chats, \"Selling weavery\"\n".to_string();
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+
+        // Already there, so no alerts
+        engine.check(&parsed, &rules, &mut [&mut sink]);
+        assert!(sink.alerts.is_empty());
+
+        log += "[16:05:05] Barry global chats, \"Selling weavery\"\n";
+        log += "[16:05:06] Barry trade chats, \"Buying weavery\"\n";
+        log += "[16:05:07] Bob tells ye, \"2 for spades\"\n";
+        log += "[16:05:08] Bob tells ye, \"spadesy\"\n";
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        engine.check(&parsed, &rules, &mut [&mut sink]);
+
+        let alerts: Vec<(&str, &AlertAction, &str)> = sink
+            .alerts
+            .iter()
+            .map(|alert| {
+                let contents = alert.message.contents.as_str();
+                (alert.rule.as_str(), &alert.action, &contents[1..9])
+            })
+            .collect();
+        assert_eq!(
+            alerts,
+            vec![
+                ("Weavery", &AlertAction::Highlight, "16:05:06"),
+                ("Weavery", &AlertAction::Sound, "16:05:06"),
+                (
+                    "Tells from Bob",
+                    &AlertAction::DesktopNotification,
+                    "16:05:07"
+                ),
+            ]
+        );
+
+        // Nothing new, nothing sent
+        engine.check(&parsed, &rules, &mut [&mut sink]);
+        assert_eq!(sink.alerts.len(), 3);
+    }
+
+    #[test]
+    fn test_no_alerts_for_a_different_chat_log() {
+        let rules = vec![AlertRule {
+            name: "Spades".to_string(),
+            text: Some("spades".to_string()),
+            highlight: true,
+            ..Default::default()
+        }];
+        let mut engine = AlertEngine::new();
+        let mut sink = RecordingSink::default();
+        let mut window_alerts = WindowAlerts::default();
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(
+            "[16:05:04] Bob tells ye, \"2 for spades\"\n".as_bytes(),
+        ));
+        engine.check(&parsed, &rules, &mut [&mut sink, &mut window_alerts]);
+
+        // A longer log, so its ids go past the last one's
+        let log = "[16:05:04] Barry tells ye, \"spades?\"\n".repeat(5);
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        engine.reset();
+        window_alerts.clear();
+        engine.check(&parsed, &rules, &mut [&mut sink, &mut window_alerts]);
+        assert!(sink.alerts.is_empty());
+        assert!(!window_alerts.is_highlighted(parsed.tells[4].id));
+    }
+
+    #[test]
+    fn test_bad_regex_never_matches() {
+        let rules = vec![AlertRule {
+            name: "Broken".to_string(),
+            regex: Some("(spades".to_string()),
+            highlight: true,
+            ..Default::default()
+        }];
+        let mut engine = AlertEngine::new();
+        let mut sink = RecordingSink::default();
+        let mut parsed = ParsedChatLog::new();
+        engine.check(&parsed, &rules, &mut [&mut sink]);
+
+        let log = "[16:05:07] Bob tells ye, \"(spades\"\n";
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        engine.check(&parsed, &rules, &mut [&mut sink]);
+        assert!(sink.alerts.is_empty());
+    }
+
+    #[test]
+    fn test_window_and_file_alerts() {
+        let path =
+            std::env::temp_dir().join(format!("chat-tracker-alerts-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let rules = vec![AlertRule {
+            name: "Tells".to_string(),
+            channel: Some(ChatType::Tell),
+            highlight: true,
+            flash_title: true,
+            append_to_file: Some(path.clone()),
+            ..Default::default()
+        }];
+        let mut engine = AlertEngine::new();
+        let mut window_alerts = WindowAlerts::default();
+        let mut system_sink = SystemAlertSink;
+        let mut parsed = ParsedChatLog::new();
+        engine.check(&parsed, &rules, &mut [&mut window_alerts, &mut system_sink]);
+
+        let log = "===== 2024/01/06 =====\n[16:05:07] Bob tells ye, \"2 for spades\"\n";
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        engine.check(&parsed, &rules, &mut [&mut window_alerts, &mut system_sink]);
+
+        assert!(window_alerts.is_highlighted(parsed.tells[0].id));
+        assert!(window_alerts.take_flash_title());
+        assert!(!window_alerts.take_flash_title());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "2024-01-06 [16:05:07] Bob tells ye, \"2 for spades\"\n"
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,1261 @@
+use std::collections::HashMap;
+use std::ops::Range;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use egui::Ui;
+
+use crate::alerts::WindowAlerts;
+use crate::appearance::{Appearance, Theme};
+#[cfg(target_arch = "wasm32")]
+use crate::chat_log::looks_like_chat_log;
+use crate::chat_log::ParsedChatLog;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::config_file::ConfigFile;
+use crate::message_list::{messages_ui, ListedMessages, MessageList, MessageListState};
+use crate::mutes::Mutes;
+use crate::npcs::Npcs;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::parser_worker::{check_chat_log, ParserCommand, ParserWorker};
+use crate::pirate_profile::{pirate_profile_ui, PirateProfile};
+use crate::search_query::{Search, SearchMode, SearchOptions, SEARCH_HELP};
+use crate::{ChatType, Config, Message, SavedSearch, Tabs, TABS, WINDOW_TITLE};
+
+const ALERT_TITLE: &str = "★ New chat alert ★";
+const TITLE_FLASH_SECONDS: f64 = 30.0;
+const MENTIONS_READ_KEY: &str = "mentions";
+const CHAT_TYPES: [ChatType; 5] = [
+    ChatType::All,
+    ChatType::Chat,
+    ChatType::Trade,
+    ChatType::Global,
+    ChatType::Tell,
+];
+
+pub struct TrackerApp {
+    config: Arc<Mutex<Config>>,
+    /// The newest snapshot from the parser
+    parsed_stuff: ParsedChatLog,
+    /// Goes up whenever parsed_stuff changes, so searches only run again when there's something
+    /// new
+    snapshot: u64,
+    /// Goes up with each snapshot and whenever what's hidden changes, so message lists get
+    /// worked out again
+    list_version: u64,
+    /// What each chat tab shows
+    chat_lists: HashMap<ChatType, ListedMessages>,
+    #[cfg(not(target_arch = "wasm32"))]
+    parser: ParserWorker,
+    /// Where changes to the config get saved. The web build has nowhere to save it.
+    #[cfg(not(target_arch = "wasm32"))]
+    config_file: ConfigFile,
+    /// Why the last picked chat log wasn't opened
+    rejected_chat_log: Option<String>,
+    selected_panel: Tabs,
+    /// The selected tab's read position from when it was opened, where the "new messages" line
+    /// goes
+    new_messages_divider: Option<(Tabs, Option<u32>)>,
+    search_tab: SearchTab,
+    /// In the same order as the saved searches in the config. Names aren't unique if the config
+    /// was edited by hand.
+    saved_search_tabs: Vec<SavedSearchTab>,
+    mentions_tab: MentionsTab,
+    /// Built from the ignore list and mute rules in the config
+    mutes: Mutes,
+    /// Whether muted messages are shown anyway
+    show_muted: bool,
+    /// Bundled and from the config
+    npcs: Npcs,
+    message_list_state: MessageListState,
+    /// The profile shown beside the tabs after a pirate's name was clicked, and the snapshot it
+    /// was built from
+    pirate_profile: Option<(PirateProfile, u64)>,
+    /// Highlights and title flashes asked for by alert rules
+    window_alerts: WindowAlerts,
+    /// When the title started flashing for an alert, in egui's time
+    title_flash_started: Option<f64>,
+    showing_alert_title: bool,
+    /// Chat logs picked in the browser are read asynchronously, then wait here to be parsed.
+    #[cfg(target_arch = "wasm32")]
+    uploaded_chat_log: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+/// What's in the Search tab, kept between frames.
+#[derive(Default)]
+struct SearchTab {
+    term: String,
+    options: SearchOptions,
+    /// Built from the search box, kept until it changes
+    search: Option<Search>,
+    /// What the search found
+    listed: ListedMessages,
+    /// Name and chat to save the search under
+    save_name: String,
+    save_channel: ChatType,
+}
+
+impl SearchTab {
+    fn search(&mut self) -> &Search {
+        if !self
+            .search
+            .as_ref()
+            .is_some_and(|search| search.is_for(&self.term, self.options))
+        {
+            self.search = Some(Search::new(&self.term, self.options));
+            self.listed = ListedMessages::default();
+        }
+        return self.search.as_ref().unwrap();
+    }
+}
+
+/// A saved search, ready to run, and how many of its results haven't been seen.
+struct SavedSearchTab {
+    saved: SavedSearch,
+    search: Search,
+    results: UnreadResults,
+    listed: ListedMessages,
+}
+
+impl SavedSearchTab {
+    fn new(saved: &SavedSearch) -> Self {
+        return SavedSearchTab {
+            saved: saved.clone(),
+            search: Search::new(&saved.query, saved.options).in_channel(saved.channel),
+            results: UnreadResults::default(),
+            listed: ListedMessages::default(),
+        };
+    }
+
+    fn read_key(&self) -> String {
+        return saved_search_read_key(&self.saved.name);
+    }
+
+    fn update_unread(
+        &mut self,
+        parsed_stuff: &mut ParsedChatLog,
+        snapshot: u64,
+        mutes: &Mutes,
+        selected: bool,
+    ) {
+        let read_key = self.read_key();
+        self.results.update(
+            &self.search,
+            &read_key,
+            parsed_stuff,
+            snapshot,
+            mutes,
+            selected,
+        );
+    }
+}
+
+/// Messages mentioning the user's pirates, and how many haven't been seen.
+struct MentionsTab {
+    pirate_names: Vec<String>,
+    search: Search,
+    results: UnreadResults,
+    listed: ListedMessages,
+}
+
+impl MentionsTab {
+    fn new(pirate_names: &[String]) -> Self {
+        return MentionsTab {
+            pirate_names: pirate_names.to_vec(),
+            search: Search::mentioning(pirate_names),
+            results: UnreadResults::default(),
+            listed: ListedMessages::default(),
+        };
+    }
+
+    fn update_unread(
+        &mut self,
+        parsed_stuff: &mut ParsedChatLog,
+        snapshot: u64,
+        mutes: &Mutes,
+        selected: bool,
+    ) {
+        self.results.update(
+            &self.search,
+            MENTIONS_READ_KEY,
+            parsed_stuff,
+            snapshot,
+            mutes,
+            selected,
+        );
+    }
+}
+
+/// A search's results, and how many haven't been seen. The search only runs again for a new
+/// snapshot, and unread results are only counted again when the tab's read position moves.
+#[derive(Default)]
+struct UnreadResults {
+    /// Ids of the results that aren't muted, from the snapshot they were found in
+    result_ids: Option<(u64, Vec<u32>)>,
+    /// Ids of all the results with their highlights, for the tab to list
+    found: Vec<(u32, Vec<Range<usize>>)>,
+    /// The read position the unread results were counted from
+    counted_from: Option<Option<u32>>,
+    unread: usize,
+}
+
+impl UnreadResults {
+    /// Marks the search's tab read if it's selected, and counts how many of its results are
+    /// unread. Muted messages don't count.
+    fn update(
+        &mut self,
+        search: &Search,
+        read_key: &str,
+        parsed_stuff: &mut ParsedChatLog,
+        snapshot: u64,
+        mutes: &Mutes,
+        selected: bool,
+    ) {
+        if self.result_ids.as_ref().map(|(found_in, _)| *found_in) != Some(snapshot) {
+            let results = search.results(parsed_stuff);
+            let result_ids = results
+                .iter()
+                .filter(|result| !mutes.is_muted(result.message))
+                .map(|result| result.message.id)
+                .collect();
+            self.result_ids = Some((snapshot, result_ids));
+            self.found = results
+                .into_iter()
+                .map(|result| (result.message.id, result.highlights))
+                .collect();
+            self.counted_from = None;
+        }
+        // The first time a search is seen, only what arrives after counts as unread
+        if selected || parsed_stuff.last_read(read_key).is_none() {
+            let newest = parsed_stuff.newest_message_id(ChatType::All);
+            parsed_stuff.mark_read(read_key, newest);
+        }
+        let last_read = parsed_stuff.last_read(read_key);
+        if self.counted_from != Some(last_read) {
+            let result_ids = self.result_ids.as_ref().map(|(_, ids)| ids.as_slice());
+            self.unread = result_ids
+                .unwrap_or_default()
+                .iter()
+                .filter(|id| Some(**id) > last_read)
+                .count();
+            self.counted_from = Some(last_read);
+        }
+    }
+
+    /// The results from the last time the search ran.
+    fn found<'a>(&self, parsed_stuff: &'a ParsedChatLog) -> Vec<(&'a Message, Vec<Range<usize>>)> {
+        return self
+            .found
+            .iter()
+            .filter_map(|(id, highlights)| {
+                let message = parsed_stuff.message_with_id(*id);
+                return message.map(|message| (message, highlights.clone()));
+            })
+            .collect();
+    }
+}
+
+fn saved_search_read_key(name: &str) -> String {
+    return format!("saved search:{}", name);
+}
+
+/// The key a tab's read position is kept under, if it has one.
+fn read_key(tab: Tabs, saved_searches: &[SavedSearch]) -> Option<String> {
+    return match tab {
+        Tabs::Chat(chat_type) => Some(chat_type.key().to_string()),
+        Tabs::Mentions => Some(MENTIONS_READ_KEY.to_string()),
+        Tabs::SavedSearch(i) => saved_searches
+            .get(i)
+            .map(|saved| saved_search_read_key(&saved.name)),
+        _ => None,
+    };
+}
+
+impl TrackerApp {
+    pub fn new(
+        config: Arc<Mutex<Config>>,
+        #[cfg(not(target_arch = "wasm32"))] parser: ParserWorker,
+        #[cfg(not(target_arch = "wasm32"))] config_file: ConfigFile,
+        window_alerts: WindowAlerts,
+    ) -> Self {
+        return TrackerApp {
+            config,
+            parsed_stuff: ParsedChatLog::new(),
+            snapshot: 0,
+            list_version: 0,
+            chat_lists: HashMap::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            parser,
+            #[cfg(not(target_arch = "wasm32"))]
+            config_file,
+            rejected_chat_log: None,
+            selected_panel: Tabs::Chat(ChatType::All),
+            new_messages_divider: None,
+            search_tab: SearchTab::default(),
+            saved_search_tabs: vec![],
+            mentions_tab: MentionsTab::new(&[]),
+            mutes: Mutes::default(),
+            show_muted: false,
+            npcs: Npcs::default(),
+            message_list_state: MessageListState::default(),
+            pirate_profile: None,
+            window_alerts,
+            title_flash_started: None,
+            showing_alert_title: false,
+            #[cfg(target_arch = "wasm32")]
+            uploaded_chat_log: Arc::new(Mutex::new(None)),
+        };
+    }
+
+    /// Keeps unread counts and read positions up to date. Whatever's in the selected tab has been
+    /// read, and each tab counts everything as read the first time it's seen.
+    fn update_unread(&mut self, config: &Config) {
+        let saved_searches = &config.saved_searches;
+        let parsed_stuff = &mut self.parsed_stuff;
+        if !self
+            .mutes
+            .is_for(&config.ignored_pirates, &config.mute_rules)
+        {
+            self.mutes = Mutes::new(&config.ignored_pirates, &config.mute_rules);
+            // Muted messages are left out of search results, so they need finding again
+            self.mentions_tab.results = UnreadResults::default();
+            for tab in &mut self.saved_search_tabs {
+                tab.results = UnreadResults::default();
+            }
+            self.list_version += 1;
+        }
+        if !self.npcs.is_for(&config.known_npcs) {
+            self.npcs = Npcs::new(&config.known_npcs);
+            self.list_version += 1;
+        }
+
+        // Capture where the selected tab was read up to before marking it read, so the divider
+        // stays put while the tab is open
+        if self.new_messages_divider.map(|(tab, _)| tab) != Some(self.selected_panel) {
+            let last_read = read_key(self.selected_panel, saved_searches)
+                .and_then(|read_key| parsed_stuff.last_read(&read_key));
+            self.new_messages_divider = Some((self.selected_panel, last_read));
+        }
+
+        // Read positions are message ids, so a tab is read up to the newest message of any chat
+        let newest = parsed_stuff.newest_message_id(ChatType::All);
+        for chat_type in CHAT_TYPES {
+            if self.selected_panel == Tabs::Chat(chat_type)
+                || parsed_stuff.last_read(chat_type.key()).is_none()
+            {
+                parsed_stuff.mark_read(chat_type.key(), newest);
+            }
+        }
+
+        self.saved_search_tabs.truncate(saved_searches.len());
+        for (i, saved) in saved_searches.iter().enumerate() {
+            match self.saved_search_tabs.get(i) {
+                Some(tab) if tab.saved == *saved => {}
+                Some(_) => self.saved_search_tabs[i] = SavedSearchTab::new(saved),
+                None => self.saved_search_tabs.push(SavedSearchTab::new(saved)),
+            }
+            self.saved_search_tabs[i].update_unread(
+                parsed_stuff,
+                self.snapshot,
+                &self.mutes,
+                self.selected_panel == Tabs::SavedSearch(i),
+            );
+        }
+
+        if self.mentions_tab.pirate_names != config.pirate_names {
+            self.mentions_tab = MentionsTab::new(&config.pirate_names);
+        }
+        self.mentions_tab.update_unread(
+            parsed_stuff,
+            self.snapshot,
+            &self.mutes,
+            self.selected_panel == Tabs::Mentions,
+        );
+    }
+
+    fn mark_all_read(&mut self) {
+        let parsed_stuff = &mut self.parsed_stuff;
+        let newest = parsed_stuff.newest_message_id(ChatType::All);
+        for chat_type in CHAT_TYPES {
+            parsed_stuff.mark_read(chat_type.key(), newest);
+        }
+        for tab in &self.saved_search_tabs {
+            parsed_stuff.mark_read(&tab.read_key(), newest);
+        }
+        parsed_stuff.mark_read(MENTIONS_READ_KEY, newest);
+        // Gets put back where everything's now read up to
+        self.new_messages_divider = None;
+    }
+
+    fn tab_label(&self, tab: Tabs, config: &Config) -> String {
+        let saved_searches = &config.saved_searches;
+        let (label, unread) = match tab {
+            Tabs::Chat(chat_type) => {
+                let parsed_stuff = &self.parsed_stuff;
+                let last_read = parsed_stuff.last_read(chat_type.key());
+                (
+                    tab.label().to_string(),
+                    parsed_stuff.unread_count(chat_type, last_read, |message| {
+                        self.mutes.is_muted(message)
+                            || (config.hide_npc_messages.contains(&chat_type)
+                                && message.is_sender_npc(&self.npcs))
+                    }),
+                )
+            }
+            Tabs::SavedSearch(i) => {
+                let unread = self.saved_search_tabs.get(i).map(|tab| tab.results.unread);
+                (saved_searches[i].name.clone(), unread.unwrap_or_default())
+            }
+            Tabs::Mentions => (tab.label().to_string(), self.mentions_tab.results.unread),
+            _ => (tab.label().to_string(), 0),
+        };
+        if unread > 0 {
+            return format!("{} ({})", label, unread);
+        }
+        return label;
+    }
+
+    /// Flashes the window title after an alert, until the window gets focus or a while passes.
+    fn flash_title(&mut self, ctx: &egui::Context) {
+        let now = ctx.input(|i| i.time);
+        if self.window_alerts.take_flash_title() {
+            self.title_flash_started = Some(now);
+            ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(
+                egui::UserAttentionType::Informational,
+            ));
+        }
+        let started = match self.title_flash_started {
+            Some(started) => started,
+            None => return,
+        };
+
+        let focused = ctx.input(|i| i.viewport().focused).unwrap_or(false);
+        let flashing = !focused && now - started < TITLE_FLASH_SECONDS;
+        let show_alert_title = flashing && (now - started) % 1.0 < 0.5;
+        if show_alert_title != self.showing_alert_title {
+            let title = if show_alert_title {
+                ALERT_TITLE
+            } else {
+                WINDOW_TITLE
+            };
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.to_string()));
+            self.showing_alert_title = show_alert_title;
+        }
+        if flashing {
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        } else {
+            self.title_flash_started = None;
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn pick_chat_log(&mut self, _ctx: &egui::Context) {
+        if let Some(path) = rfd::FileDialog::new().pick_file() {
+            self.open_chat_log(path);
+        }
+    }
+
+    /// Only one chat log is tracked at a time, so if several are dropped the first is opened.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn open_dropped_chat_log(&mut self, ctx: &egui::Context) {
+        let dropped_chat_log = ctx.input(|i| {
+            i.raw
+                .dropped_files
+                .iter()
+                .find_map(|file| file.path.clone())
+        });
+        if let Some(path) = dropped_chat_log {
+            self.open_chat_log(path);
+        }
+    }
+
+    /// Picked and dropped chat logs are checked before they're opened.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn open_chat_log(&mut self, path: PathBuf) {
+        if let Err(e) = check_chat_log(&path) {
+            self.rejected_chat_log = Some(e);
+            return;
+        }
+        self.rejected_chat_log = None;
+        // Opened once the config's changed
+        self.config.lock().unwrap().chat_log_path = Some(path);
+    }
+
+    /// Merges in hand edits to the config file, and saves changes made in the window.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn sync_config(&mut self, config: Config, original_config: &Config) -> Config {
+        if !self.config_file.has_changed() && config == *original_config {
+            return config;
+        }
+        let config = self.config_file.sync(&config);
+        *self.config.lock().unwrap() = config.clone();
+        return config;
+    }
+
+    /// Reads the config file again when asked, in case it's been fixed without a change being
+    /// noticed.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn config_error_ui(&mut self, ui: &mut Ui) {
+        let error = match &self.config_file.error {
+            Some(error) => error,
+            None => return,
+        };
+        let retry_hint = "Changes won't be saved until it's fixed.";
+        if error_banner_ui(ui, error, Some(retry_hint)) {
+            let mut config = self.config.lock().unwrap();
+            *config = self.config_file.sync(&config);
+        }
+    }
+
+    /// Reloading, pausing, and how far the parser's got.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn parser_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Reload chat log").clicked() {
+                self.parser.send(ParserCommand::Reload);
+            }
+            let mut paused = self.parser.paused;
+            if ui.checkbox(&mut paused, "Pause updates").changed() {
+                self.parser.send(ParserCommand::Pause(paused));
+            }
+            if let Some(lines) = self.parser.progress {
+                ui.spinner();
+                ui.label(format!("Reading chat log, {} lines so far", lines));
+            }
+        });
+        if let Some(error) = &self.parser.error {
+            let retry_hint = (!self.parser.stopped).then_some(
+                "It'll be tried again when the chat log changes. If it's moved, hit 'Open chat log' to find it.",
+            );
+            if error_banner_ui(ui, error, retry_hint) {
+                self.parser.send(ParserCommand::Reload);
+            }
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn pick_chat_log(&mut self, ctx: &egui::Context) {
+        let uploaded_chat_log = self.uploaded_chat_log.clone();
+        let ctx = ctx.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Some(file) = rfd::AsyncFileDialog::new().pick_file().await {
+                *uploaded_chat_log.lock().unwrap() = Some(file.read().await);
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    /// Parses a chat log that's been picked or dropped onto the page, replacing whatever was
+    /// there before. Everything stays in the browser.
+    #[cfg(target_arch = "wasm32")]
+    fn parse_uploaded_chat_log(&mut self, ctx: &egui::Context) {
+        let dropped_chat_log = ctx.input(|i| {
+            i.raw
+                .dropped_files
+                .first()
+                .and_then(|file| file.bytes.clone())
+        });
+        if let Some(bytes) = dropped_chat_log {
+            *self.uploaded_chat_log.lock().unwrap() = Some(bytes.to_vec());
+        }
+
+        if let Some(bytes) = self.uploaded_chat_log.lock().unwrap().take() {
+            if !looks_like_chat_log(std::io::BufReader::new(bytes.as_slice())) {
+                self.rejected_chat_log =
+                    Some("That file doesn't look like a Puzzle Pirates chat log".to_string());
+                return;
+            }
+            self.rejected_chat_log = None;
+            let mut parsed = ParsedChatLog::new();
+            parsed.parse_chat_log(std::io::BufReader::new(bytes.as_slice()));
+            self.parsed_stuff = parsed;
+            self.snapshot += 1;
+            self.list_version += 1;
+        }
+    }
+}
+
+impl eframe::App for TrackerApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        #[cfg(target_arch = "wasm32")]
+        self.parse_uploaded_chat_log(ctx);
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.parser.receive(&mut self.parsed_stuff) {
+            self.snapshot += 1;
+            self.list_version += 1;
+        }
+
+        self.flash_title(ctx);
+
+        let original_config = self.config.lock().unwrap().clone();
+        if let Tabs::SavedSearch(i) = self.selected_panel {
+            if i >= original_config.saved_searches.len() {
+                self.selected_panel = Tabs::SearchChat;
+            }
+        }
+        self.update_unread(&original_config);
+
+        if let Some((profile, built_from)) = &mut self.pirate_profile {
+            if *built_from != self.snapshot {
+                let rebuilt = PirateProfile::new(&self.parsed_stuff, &profile.pirate);
+                *profile = rebuilt;
+                *built_from = self.snapshot;
+            }
+        }
+        let mut close_profile = false;
+        if let Some((profile, _)) = &mut self.pirate_profile {
+            egui::SidePanel::right("pirate_profile").show(ctx, |ui| {
+                let list = MessageList {
+                    message_limit: (original_config.message_limit.0 as usize).max(1),
+                    window_alerts: &self.window_alerts,
+                    mutes: &self.mutes,
+                    show_muted: self.show_muted,
+                    npcs: &self.npcs,
+                    appearance: &original_config.appearance,
+                    hide_npcs: false,
+                    new_messages_after: None,
+                    newest_at_bottom: false,
+                    version: self.list_version,
+                };
+                close_profile = pirate_profile_ui(
+                    ui,
+                    profile,
+                    &self.parsed_stuff,
+                    &mut self.config.lock().unwrap().pirate_notes,
+                    &list,
+                    &mut self.message_list_state,
+                );
+            });
+        }
+        if close_profile {
+            self.pirate_profile = None;
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            #[cfg(not(target_arch = "wasm32"))]
+            self.config_error_ui(ui);
+            if self.config.lock().unwrap().chat_log_path.is_none() {
+                if cfg!(target_arch = "wasm32") {
+                    ui.label("No chat log given. Please hit 'Open chat log', or drop your chat log onto the page. It won't leave your browser.");
+                } else {
+                    ui.label("No chat log given. Please hit 'Open chat log', or drop your chat log onto the window.");
+                }
+            }
+            if ui.button("Open chat log").clicked() {
+                self.pick_chat_log(ctx);
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            self.open_dropped_chat_log(ctx);
+            if let Some(rejected) = &self.rejected_chat_log {
+                ui.colored_label(ui.visuals().error_fg_color, rejected);
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            if original_config.chat_log_path.is_some() {
+                self.parser_ui(ui);
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("Mark all read").clicked() {
+                    self.mark_all_read();
+                }
+                ui.checkbox(&mut self.show_muted, "Show muted messages");
+            });
+
+            let saved_search_tabs = (0..original_config.saved_searches.len()).map(Tabs::SavedSearch);
+            ui.horizontal_wrapped(|ui| {
+                for tab in TABS.into_iter().chain(saved_search_tabs) {
+                    let label = self.tab_label(tab, &original_config);
+                    ui.selectable_value(&mut self.selected_panel, tab, label);
+                }
+            });
+            let list = MessageList {
+                // Always a page to show
+                message_limit: (self.config.lock().unwrap().message_limit.0 as usize).max(1),
+                window_alerts: &self.window_alerts,
+                mutes: &self.mutes,
+                show_muted: self.show_muted,
+                npcs: &self.npcs,
+                appearance: &original_config.appearance,
+                hide_npcs: match self.selected_panel {
+                    Tabs::Chat(chat_type) => original_config.hide_npc_messages.contains(&chat_type),
+                    _ => false,
+                },
+                new_messages_after: self.new_messages_divider.and_then(|(_, last_read)| last_read),
+                newest_at_bottom: matches!(self.selected_panel, Tabs::Chat(_))
+                    && original_config.newest_at_bottom,
+                version: self.list_version,
+            };
+            match self.selected_panel {
+                Tabs::GreedyHits => greedy_ui(ui, &self.parsed_stuff),
+                Tabs::Chat(chat_type) => chat_ui(
+                    ui,
+                    &self.parsed_stuff,
+                    chat_type,
+                    self.chat_lists.entry(chat_type).or_default(),
+                    &list,
+                    &mut self.message_list_state,
+                ),
+                Tabs::Mentions => mentions_ui(
+                    ui,
+                    &self.parsed_stuff,
+                    &mut self.mentions_tab,
+                    &list,
+                    &mut self.message_list_state,
+                ),
+                Tabs::SearchChat => search_chat_ui(
+                    ui,
+                    &self.parsed_stuff,
+                    &mut self.search_tab,
+                    &mut self.config.lock().unwrap().saved_searches,
+                    &list,
+                    &mut self.message_list_state,
+                ),
+                Tabs::SavedSearch(i) => {
+                    let saved = &original_config.saved_searches[i];
+                    let delete = saved_search_ui(
+                        ui,
+                        &self.parsed_stuff,
+                        &mut self.saved_search_tabs[i],
+                        &list,
+                        &mut self.message_list_state,
+                    );
+                    if delete {
+                        self.parsed_stuff
+                            .read_positions
+                            .remove(&saved_search_read_key(&saved.name));
+                        self.parsed_stuff.read_positions_changed = true;
+                        self.config.lock().unwrap().saved_searches.remove(i);
+                        self.selected_panel = Tabs::SearchChat;
+                    }
+                }
+                Tabs::Settings => {
+                    settings_ui(ui, &mut self.config.lock().unwrap(), &self.mutes)
+                }
+            }
+        });
+
+        drop_overlay(ctx);
+        if let Some(pirate) = self.message_list_state.clicked_pirate.take() {
+            let profile = PirateProfile::new(&self.parsed_stuff, &pirate);
+            self.pirate_profile = Some((profile, self.snapshot));
+            // The panel goes beside the tabs, which have already been shown this frame
+            ctx.request_repaint();
+        }
+
+        let config = self.config.lock().unwrap().clone();
+        #[cfg(not(target_arch = "wasm32"))]
+        let config = self.sync_config(config, &original_config);
+        if config.appearance != original_config.appearance {
+            config.appearance.apply(ctx);
+        }
+        if config.appearance.custom_font != original_config.appearance.custom_font {
+            config.appearance.load_font(ctx);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if config.chat_log_path != original_config.chat_log_path
+            || config.archive_dir != original_config.archive_dir
+        {
+            if let Some(open) = ParserCommand::open(&config) {
+                // Nothing from the old chat log should show while the new one's read
+                self.parsed_stuff = ParsedChatLog::new();
+                self.parser.send(open);
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if config.poll_interval_ms != original_config.poll_interval_ms {
+            self.parser.send(ParserCommand::SetInterval(
+                config.poll_interval_ms.duration(),
+            ));
+        }
+    }
+}
+
+/// Covers the window while a file's dragged over it, so it's clear dropping it will open it.
+fn drop_overlay(ctx: &egui::Context) {
+    let hovered_files = ctx.input(|i| i.raw.hovered_files.len());
+    if hovered_files == 0 {
+        return;
+    }
+    let text = if hovered_files == 1 {
+        "Drop to open this chat log"
+    } else {
+        "Drop to open the first of these chat logs"
+    };
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("drop_overlay"),
+    ));
+    let screen_rect = ctx.screen_rect();
+    painter.rect_filled(screen_rect, 0.0, egui::Color32::from_black_alpha(192));
+    painter.text(
+        screen_rect.center(),
+        egui::Align2::CENTER_CENTER,
+        text,
+        egui::TextStyle::Heading.resolve(&ctx.style()),
+        egui::Color32::WHITE,
+    );
+}
+
+/// Says what went wrong, with a button to try again if there's a hint for it. Returns whether it
+/// was clicked.
+#[cfg(not(target_arch = "wasm32"))]
+fn error_banner_ui(ui: &mut Ui, error: &str, retry_hint: Option<&str>) -> bool {
+    let error_colour = ui.visuals().error_fg_color;
+    let mut clicked = false;
+    egui::Frame::group(ui.style())
+        .stroke(egui::Stroke::new(1.0, error_colour))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            ui.colored_label(error_colour, format!("⚠ {}", error));
+            if let Some(retry_hint) = retry_hint {
+                ui.horizontal(|ui| {
+                    ui.label(retry_hint);
+                    clicked = ui.button("Retry").clicked();
+                });
+            }
+        });
+    return clicked;
+}
+
+fn settings_ui(ui: &mut Ui, config: &mut Config, mutes: &Mutes) {
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        ui.label("Messages per page");
+        let mut tmp = config.message_limit.0.to_string();
+        ui.text_edit_singleline(&mut tmp);
+
+        if let Ok(new_message_limit) = tmp.parse::<u64>() {
+            config.message_limit.0 = new_message_limit;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.horizontal(|ui| {
+            ui.label("If changes can't be watched for, check the chat log every");
+            ui.add(
+                egui::DragValue::new(&mut config.poll_interval_ms.0)
+                    .clamp_range(100..=60_000)
+                    .suffix(" ms"),
+            );
+        });
+        ui.checkbox(
+            &mut config.newest_at_bottom,
+            "Newest messages at the bottom of chat tabs",
+        );
+
+        ui.separator();
+        ui.label("Your pirates, so messages naming them show up in Mentions");
+        text_list_ui(ui, &mut config.pirate_names, "Add pirate", |_| None);
+
+        ui.separator();
+        ui.label("Ignored pirates, whose messages are hidden");
+        text_list_ui(ui, &mut config.ignored_pirates, "Ignore pirate", |_| None);
+
+        ui.separator();
+        ui.label("Hide NPC messages in");
+        ui.horizontal_wrapped(|ui| {
+            for chat_type in CHAT_TYPES {
+                let mut hidden = config.hide_npc_messages.contains(&chat_type);
+                if ui
+                    .checkbox(&mut hidden, Tabs::Chat(chat_type).label())
+                    .changed()
+                {
+                    config
+                        .hide_npc_messages
+                        .retain(|hiding| *hiding != chat_type);
+                    if hidden {
+                        config.hide_npc_messages.push(chat_type);
+                    }
+                }
+            }
+        });
+        ui.label("NPCs with one word names, on top of the ones the tracker knows about");
+        text_list_ui(ui, &mut config.known_npcs, "Add NPC", |_| None);
+
+        ui.separator();
+        appearance_ui(ui, &mut config.appearance);
+
+        ui.separator();
+        ui.label("Mute rules. Messages matching any of these regular expressions are hidden");
+        text_list_ui(ui, &mut config.mute_rules, "Add mute rule", |i| {
+            mutes.rule_error(i)
+        });
+    });
+}
+
+fn appearance_ui(ui: &mut Ui, appearance: &mut Appearance) {
+    ui.heading("Appearance");
+    ui.horizontal(|ui| {
+        ui.radio_value(&mut appearance.theme, Theme::Dark, "Dark");
+        ui.radio_value(&mut appearance.theme, Theme::Light, "Light");
+    });
+    ui.add(egui::Slider::new(&mut appearance.font_scale, 0.5..=3.0).text("Font scale"));
+
+    for chat_type in CHAT_TYPES {
+        let colour = match appearance.chat_type_colour_mut(chat_type) {
+            Some(colour) => colour,
+            None => continue,
+        };
+        ui.horizontal(|ui| {
+            ui.label(format!("{} colour", Tabs::Chat(chat_type).label()));
+            ui.color_edit_button_srgb(&mut colour.0);
+        });
+    }
+    ui.horizontal(|ui| {
+        ui.label("NPC name colour");
+        ui.color_edit_button_srgb(&mut appearance.npc_name_colour.0);
+    });
+
+    // There's no reading font files from the browser
+    #[cfg(not(target_arch = "wasm32"))]
+    ui.horizontal(|ui| {
+        ui.label("Custom font");
+        let mut font_path = appearance
+            .custom_font
+            .as_ref()
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if ui.text_edit_singleline(&mut font_path).lost_focus() {
+            appearance.custom_font = if font_path.trim().is_empty() {
+                None
+            } else {
+                Some(PathBuf::from(font_path.trim()))
+            };
+        }
+        if ui.button("Pick font").clicked() {
+            if let Some(path) = rfd::FileDialog::new()
+                .add_filter("Fonts", &["ttf", "otf"])
+                .pick_file()
+            {
+                appearance.custom_font = Some(path);
+            }
+        }
+    });
+}
+
+/// An editable list of text, with any problems with each entry shown under it.
+fn text_list_ui<'a>(
+    ui: &mut Ui,
+    entries: &mut Vec<String>,
+    add_label: &str,
+    error: impl Fn(usize) -> Option<&'a str>,
+) {
+    let mut removed = None;
+    for (i, entry) in entries.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(entry);
+            if ui.button("Remove").clicked() {
+                removed = Some(i);
+            }
+        });
+        if let Some(e) = error(i) {
+            ui.colored_label(ui.visuals().error_fg_color, e);
+        }
+    }
+    if let Some(i) = removed {
+        entries.remove(i);
+    }
+    if ui.button(add_label).clicked() {
+        entries.push(String::new());
+    }
+}
+
+fn mentions_ui(
+    ui: &mut Ui,
+    parsed_stuff: &ParsedChatLog,
+    tab: &mut MentionsTab,
+    list: &MessageList,
+    state: &mut MessageListState,
+) {
+    ui.heading(Tabs::Mentions.label());
+    if tab.pirate_names.iter().all(|name| name.trim().is_empty()) {
+        ui.label("Add your pirate's name in Settings to see messages that mention them.");
+        return;
+    }
+    search_results_ui(
+        ui,
+        Tabs::Mentions,
+        parsed_stuff,
+        &mut tab.listed,
+        list,
+        state,
+        || tab.results.found(parsed_stuff),
+    );
+}
+
+fn search_chat_ui(
+    ui: &mut Ui,
+    parsed_stuff: &ParsedChatLog,
+    search_tab: &mut SearchTab,
+    saved_searches: &mut Vec<SavedSearch>,
+    list: &MessageList,
+    state: &mut MessageListState,
+) {
+    ui.heading("Filtered chat");
+    let search_label = ui.label("Search term");
+    ui.text_edit_singleline(&mut search_tab.term)
+        .labelled_by(search_label.id);
+    let search_options = &mut search_tab.options;
+    ui.horizontal(|ui| {
+        ui.radio_value(&mut search_options.mode, SearchMode::Query, "Query");
+        ui.radio_value(&mut search_options.mode, SearchMode::Regex, "Regex");
+        ui.radio_value(&mut search_options.mode, SearchMode::Fuzzy, "Fuzzy");
+        ui.separator();
+        // Fuzzy searches already ignore case and only compare whole words
+        let exact = search_options.mode != SearchMode::Fuzzy;
+        ui.add_enabled(
+            exact,
+            egui::Checkbox::new(&mut search_options.whole_word, "Whole words"),
+        );
+        ui.add_enabled(
+            exact,
+            egui::Checkbox::new(&mut search_options.case_sensitive, "Match case"),
+        );
+    });
+    let hint = match search_options.mode {
+        SearchMode::Query => SEARCH_HELP,
+        SearchMode::Regex => "The whole search is a regular expression, like weave?r(y|ie)",
+        SearchMode::Fuzzy => "Finds words spelt roughly the same, fewest typos first",
+    };
+    ui.label(egui::RichText::new(hint).weak().small());
+
+    if let Some(e) = search_tab.search().error() {
+        ui.colored_label(ui.visuals().error_fg_color, e);
+        return;
+    }
+
+    ui.horizontal(|ui| {
+        let name_label = ui.label("Save as");
+        ui.text_edit_singleline(&mut search_tab.save_name)
+            .labelled_by(name_label.id);
+        egui::ComboBox::from_id_source("save_search_channel")
+            .selected_text(Tabs::Chat(search_tab.save_channel).label())
+            .show_ui(ui, |ui| {
+                for chat_type in CHAT_TYPES {
+                    ui.selectable_value(
+                        &mut search_tab.save_channel,
+                        chat_type,
+                        Tabs::Chat(chat_type).label(),
+                    );
+                }
+            });
+        let can_save =
+            !search_tab.save_name.trim().is_empty() && !search_tab.term.trim().is_empty();
+        if ui
+            .add_enabled(can_save, egui::Button::new("Save search"))
+            .clicked()
+        {
+            let saved = SavedSearch {
+                name: search_tab.save_name.trim().to_string(),
+                query: search_tab.term.clone(),
+                channel: search_tab.save_channel,
+                options: search_tab.options,
+            };
+            // Saving under an existing name replaces that search
+            match saved_searches.iter_mut().find(|s| s.name == saved.name) {
+                Some(existing) => *existing = saved,
+                None => saved_searches.push(saved),
+            }
+            search_tab.save_name.clear();
+        }
+    });
+
+    // Searches aren't read, so there's no new messages line
+    let list = MessageList {
+        new_messages_after: None,
+        ..*list
+    };
+    let search = search_tab.search.as_ref().unwrap();
+    search_results_ui(
+        ui,
+        Tabs::SearchChat,
+        parsed_stuff,
+        &mut search_tab.listed,
+        &list,
+        state,
+        || {
+            return search
+                .results(parsed_stuff)
+                .into_iter()
+                .map(|result| (result.message, result.highlights))
+                .collect();
+        },
+    );
+}
+
+/// Returns true if the user asked to delete the saved search.
+fn saved_search_ui(
+    ui: &mut Ui,
+    parsed_stuff: &ParsedChatLog,
+    tab: &mut SavedSearchTab,
+    list: &MessageList,
+    state: &mut MessageListState,
+) -> bool {
+    ui.heading(&tab.saved.name);
+    let delete = ui
+        .horizontal(|ui| {
+            ui.label(format!(
+                "{} in {}",
+                tab.saved.query,
+                Tabs::Chat(tab.saved.channel).label()
+            ));
+            return ui.button("Delete").clicked();
+        })
+        .inner;
+    if let Some(e) = tab.search.error() {
+        ui.colored_label(ui.visuals().error_fg_color, e);
+        return delete;
+    }
+    let id_source = tab.read_key();
+    if tab.saved.options.mode == SearchMode::Fuzzy {
+        // Best matches come first rather than newest, so there's nowhere for the line to go
+        let list = MessageList {
+            new_messages_after: None,
+            ..*list
+        };
+        search_results_ui(
+            ui,
+            id_source,
+            parsed_stuff,
+            &mut tab.listed,
+            &list,
+            state,
+            || tab.results.found(parsed_stuff),
+        );
+    } else {
+        search_results_ui(
+            ui,
+            id_source,
+            parsed_stuff,
+            &mut tab.listed,
+            list,
+            state,
+            || tab.results.found(parsed_stuff),
+        );
+    }
+    return delete;
+}
+
+/// Lists a search's results, only asking `results` for them when they might have changed.
+fn search_results_ui<'a>(
+    ui: &mut Ui,
+    id_source: impl std::hash::Hash,
+    parsed_stuff: &'a ParsedChatLog,
+    listed: &mut ListedMessages,
+    list: &MessageList,
+    state: &mut MessageListState,
+    results: impl FnOnce() -> Vec<(&'a Message, Vec<Range<usize>>)>,
+) {
+    listed.update(list, results);
+    messages_ui(ui, id_source, parsed_stuff, listed, list, state);
+}
+
+fn chat_ui(
+    ui: &mut Ui,
+    parsed_stuff: &ParsedChatLog,
+    chat_type: ChatType,
+    listed: &mut ListedMessages,
+    list: &MessageList,
+    state: &mut MessageListState,
+) {
+    ui.heading(Tabs::Chat(chat_type).label());
+    listed.update(list, || {
+        return parsed_stuff
+            .messages_of_type(chat_type)
+            .into_iter()
+            .rev()
+            .map(|message| (message, vec![]))
+            .collect();
+    });
+    messages_ui(ui, Tabs::Chat(chat_type), parsed_stuff, listed, list, state);
+}
+
+fn greedy_ui(ui: &mut Ui, parsed_stuff: &ParsedChatLog) {
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        if parsed_stuff.battles.is_empty() {
+            ui.label("No battles detected.");
+        } else {
+            ui.heading("Greedy hits");
+            for battle in &parsed_stuff.battles {
+                ui.separator();
+                ui.heading(format!(
+                    "Battle between {} and {}",
+                    battle.attacker_ship, battle.defender_ship
+                ));
+                let greedy_count: u32 = battle.greedies.values().sum();
+                let total_greedy_hits_str = format!("{} Greedies in total", greedy_count);
+                ui.label(&total_greedy_hits_str);
+                if battle.greedies.is_empty() {
+                    ui.label("No Greedies for this battle");
+                } else {
+                    let mut sorted_results: Vec<(&String, &u32)> = battle.greedies.iter().collect();
+                    sorted_results.sort_by(|a, b| b.1.cmp(a.1));
+
+                    let mut greedy_clipboard_text = String::new();
+                    greedy_clipboard_text.push_str(&total_greedy_hits_str);
+                    greedy_clipboard_text += ". ";
+
+                    for (i, entry) in sorted_results.iter().enumerate() {
+                        let s = if i == sorted_results.len() - 1 {
+                            format!("{}: {}", entry.0, entry.1)
+                        } else {
+                            format!("{}: {}, ", entry.0, entry.1)
+                        };
+                        greedy_clipboard_text.push_str(&s);
+                    }
+
+                    if ui.button("Copy me!").clicked() {
+                        ui.output_mut(|o| o.copied_text = greedy_clipboard_text);
+                    }
+
+                    for entry in &sorted_results {
+                        ui.label(format!("{} got {}", entry.0, entry.1));
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use super::SavedSearchTab;
+    use crate::chat_log::ParsedChatLog;
+    use crate::mutes::Mutes;
+    use crate::search_query::SearchOptions;
+    use crate::{ChatType, SavedSearch};
+
+    #[test]
+    fn test_saved_search_unread() {
+        let saved = SavedSearch {
+            name: "Iron".to_string(),
+            query: "iron".to_string(),
+            channel: ChatType::Trade,
+            options: SearchOptions::default(),
+        };
+        let mut log = "[16:05:04] Bob trade chats, \"Buying iron\"\n".to_string();
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+
+        // What's already there when the tab appears has been seen
+        let mutes = Mutes::default();
+        let mut tab = SavedSearchTab::new(&saved);
+        tab.update_unread(&mut parsed, 0, &mutes, false);
+        assert_eq!(tab.results.unread, 0);
+
+        log += "[16:05:05] Bob trade chats, \"Selling iron\"\n";
+        log += "[16:05:06] Bob global chats, \"Iron anyone?\"\n";
+        log += "[16:05:07] Spammer trade chats, \"Iron iron iron\"\n";
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        // Muted messages never count
+        let mutes = Mutes::new(&["Spammer".to_string()], &[]);
+        tab.update_unread(&mut parsed, 1, &mutes, false);
+        assert_eq!(tab.results.unread, 1);
+        // The tab still lists them, for showing muted messages
+        assert_eq!(tab.results.found(&parsed).len(), 3);
+
+        // Looking at the tab reads everything
+        tab.update_unread(&mut parsed, 1, &mutes, true);
+        assert_eq!(tab.results.unread, 0);
+        assert_eq!(parsed.last_read("saved search:Iron"), Some(4));
+    }
+}
@@ -0,0 +1,176 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ChatType;
+
+const CUSTOM_FONT: &str = "custom";
+
+/// How the window looks. Saved in the config and applied on startup.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Appearance {
+    pub theme: Theme,
+    /// Multiplies the size of all text
+    pub font_scale: f32,
+    /// A .ttf or .otf file used for all text instead of the built in font. Not available on the
+    /// web.
+    pub custom_font: Option<PathBuf>,
+    /// Colours for what was said in each chat
+    pub chat_colour: Colour,
+    pub trade_colour: Colour,
+    pub global_colour: Colour,
+    pub tell_colour: Colour,
+    pub npc_name_colour: Colour,
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        return Appearance {
+            theme: Theme::Dark,
+            font_scale: 1.0,
+            custom_font: None,
+            // Readable on light and dark backgrounds
+            chat_colour: Colour([0x9A, 0x9A, 0x9A]),
+            trade_colour: Colour([0x3C, 0xB3, 0x71]),
+            global_colour: Colour([0x4A, 0x90, 0xE2]),
+            tell_colour: Colour([0xC0, 0x6C, 0xD8]),
+            npc_name_colour: Colour([0xFF, 0x45, 0x00]),
+        };
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+/// Written as a hex code like "#FF4500" in the config.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Colour(pub [u8; 3]);
+
+impl TryFrom<String> for Colour {
+    type Error = String;
+
+    fn try_from(hex: String) -> Result<Self, Self::Error> {
+        let colour = egui::Color32::from_hex(&hex)
+            .map_err(|_| format!("{} isn't a colour, expected a hex code like #FF4500", hex))?;
+        return Ok(Colour([colour.r(), colour.g(), colour.b()]));
+    }
+}
+
+impl From<Colour> for String {
+    fn from(colour: Colour) -> Self {
+        let [r, g, b] = colour.0;
+        return format!("#{:02X}{:02X}{:02X}", r, g, b);
+    }
+}
+
+impl Colour {
+    pub fn color32(&self) -> egui::Color32 {
+        let [r, g, b] = self.0;
+        return egui::Color32::from_rgb(r, g, b);
+    }
+}
+
+impl Appearance {
+    /// None for All, since every message is in a particular chat.
+    pub fn chat_type_colour(&self, chat_type: ChatType) -> Option<Colour> {
+        return match chat_type {
+            ChatType::Chat => Some(self.chat_colour),
+            ChatType::Trade => Some(self.trade_colour),
+            ChatType::Global => Some(self.global_colour),
+            ChatType::Tell => Some(self.tell_colour),
+            ChatType::All => None,
+        };
+    }
+
+    pub fn chat_type_colour_mut(&mut self, chat_type: ChatType) -> Option<&mut Colour> {
+        return match chat_type {
+            ChatType::Chat => Some(&mut self.chat_colour),
+            ChatType::Trade => Some(&mut self.trade_colour),
+            ChatType::Global => Some(&mut self.global_colour),
+            ChatType::Tell => Some(&mut self.tell_colour),
+            ChatType::All => None,
+        };
+    }
+
+    /// Sets the theme and text size.
+    pub fn apply(&self, ctx: &egui::Context) {
+        ctx.set_visuals(match self.theme {
+            Theme::Dark => egui::Visuals::dark(),
+            Theme::Light => egui::Visuals::light(),
+        });
+        let default_text_styles = egui::Style::default().text_styles;
+        // Tiny or huge text makes the settings impossible to get back to
+        let font_scale = self.font_scale.clamp(0.5, 3.0);
+        ctx.style_mut(|style| {
+            for (text_style, font_id) in style.text_styles.iter_mut() {
+                if let Some(default_font_id) = default_text_styles.get(text_style) {
+                    font_id.size = default_font_id.size * font_scale;
+                }
+            }
+        });
+    }
+
+    /// Uses the custom font for all text, or the built in fonts if there isn't one. Falls back
+    /// to the built in fonts if it can't be read.
+    pub fn load_font(&self, ctx: &egui::Context) {
+        let mut fonts = egui::FontDefinitions::default();
+        if let Some(path) = &self.custom_font {
+            match read_font(path) {
+                Ok(data) => {
+                    fonts
+                        .font_data
+                        .insert(CUSTOM_FONT.to_string(), egui::FontData::from_owned(data));
+                    // Built in fonts stay after it, for anything it doesn't have
+                    for family in fonts.families.values_mut() {
+                        family.insert(0, CUSTOM_FONT.to_string());
+                    }
+                }
+                Err(e) => eprintln!("Couldn't load font from {}: {}", path.to_string_lossy(), e),
+            }
+        }
+        ctx.set_fonts(fonts);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_font(path: &std::path::Path) -> Result<Vec<u8>, String> {
+    return std::fs::read(path).map_err(|e| e.to_string());
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_font(_path: &std::path::Path) -> Result<Vec<u8>, String> {
+    return Err("Fonts can't be loaded from files on the web".to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Appearance, Colour, Theme};
+
+    #[test]
+    fn test_appearance_in_config() {
+        let appearance: Appearance = toml::from_str(
+            "theme = \"light\"
+font_scale = 1.5
+trade_colour = \"#00ff00\"",
+        )
+        .unwrap();
+        assert_eq!(appearance.theme, Theme::Light);
+        assert_eq!(appearance.font_scale, 1.5);
+        assert_eq!(appearance.trade_colour, Colour([0, 0xFF, 0]));
+        // Anything not given is the default
+        assert_eq!(appearance.npc_name_colour, Colour([0xFF, 0x45, 0x00]));
+
+        let written = toml::to_string(&appearance).unwrap();
+        assert!(written.contains("trade_colour = \"#00FF00\""));
+        assert_eq!(toml::from_str::<Appearance>(&written).unwrap(), appearance);
+
+        let error = toml::from_str::<Appearance>("tell_colour = \"purple\"").unwrap_err();
+        assert!(error.to_string().contains("purple isn't a colour"));
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use time::Date;
+
+use crate::chat_log::ParsedChatLog;
+use crate::{Battle, Message};
+
+const MESSAGES_FILE: &str = "messages.jsonl";
+const BATTLES_FILE: &str = "battles.json";
+const STATE_FILE: &str = "state.json";
+
+/// Keeps what's been parsed from a chat log on disk, so history survives restarts and the game
+/// clearing out its chat log.
+///
+/// Messages are only ever appended. Battles and the parser's place in the chat log are small, so
+/// they're rewritten each save.
+pub struct Archive {
+    dir: PathBuf,
+}
+
+/// Where the parser got up to, so a restart only has to parse what's new.
+#[derive(Serialize, Deserialize, Default)]
+struct ArchiveState {
+    last_line_read: usize,
+    total_lines_read: usize,
+    current_date: Option<Date>,
+    in_battle: bool,
+    first_line: Option<String>,
+    #[serde(default)]
+    read_positions: BTreeMap<String, u32>,
+}
+
+impl Archive {
+    /// Each chat log gets its own archive under `archive_root`.
+    pub fn for_chat_log(archive_root: &Path, chat_log_path: &Path) -> Archive {
+        let name = chat_log_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        // Two logs can share a name, so tell them apart with the full path too
+        let dir_name = format!("{}-{:016x}", name, fnv1a(&chat_log_path.to_string_lossy()));
+        return Archive {
+            dir: archive_root.join(dir_name),
+        };
+    }
+
+    /// Everything archived so far, ready to carry on parsing the chat log from where we left off.
+    /// Gives an empty log if nothing has been archived yet.
+    pub fn load(&self) -> Result<ParsedChatLog, std::io::Error> {
+        let mut parsed = ParsedChatLog::new();
+
+        let messages_path = self.dir.join(MESSAGES_FILE);
+        if messages_path.exists() {
+            let reader = BufReader::new(File::open(&messages_path)?);
+            for line in reader.lines() {
+                // A line cut short by a crash mid write isn't worth losing the whole archive over.
+                // The parser will pick the message up again from the chat log if it's still there.
+                match serde_json::from_str::<Message>(&line?) {
+                    Ok(message) => {
+                        parsed.last_archived_id = Some(message.id);
+                        parsed.push_message(message);
+                    }
+                    Err(e) => eprintln!("Skipping unreadable archived message: {}", e),
+                }
+            }
+        }
+
+        if let Some(battles) = read_json::<VecDeque<Battle>>(&self.dir.join(BATTLES_FILE))? {
+            // Stored newest first, the same as the parsed chat log
+            for battle in battles.into_iter().rev() {
+                parsed.push_battle(battle);
+            }
+        }
+
+        if let Some(state) = read_json::<ArchiveState>(&self.dir.join(STATE_FILE))? {
+            parsed.last_line_read = state.last_line_read;
+            parsed.total_lines_read = state.total_lines_read;
+            parsed.current_date = state.current_date;
+            parsed.in_battle = state.in_battle;
+            parsed.first_line = state.first_line;
+            parsed.read_positions = state.read_positions;
+        }
+
+        return Ok(parsed);
+    }
+
+    /// Appends any messages that haven't been archived yet, and updates the battles and the
+    /// parser's place in the chat log.
+    pub fn save(&self, parsed: &mut ParsedChatLog) -> Result<(), std::io::Error> {
+        fs::create_dir_all(&self.dir)?;
+
+        let new_messages = parsed.messages_after(parsed.last_archived_id);
+        if let Some(newest) = new_messages.last() {
+            let newest_id = newest.id;
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.dir.join(MESSAGES_FILE))?;
+            let mut writer = BufWriter::new(file);
+            for message in new_messages {
+                serde_json::to_writer(&mut writer, message)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+            parsed.last_archived_id = Some(newest_id);
+        }
+
+        write_json_atomically(&self.dir.join(BATTLES_FILE), &parsed.battles)?;
+
+        let state = ArchiveState {
+            last_line_read: parsed.last_line_read,
+            total_lines_read: parsed.total_lines_read,
+            current_date: parsed.current_date,
+            in_battle: parsed.in_battle,
+            first_line: parsed.first_line.clone(),
+            read_positions: parsed.read_positions.clone(),
+        };
+        write_json_atomically(&self.dir.join(STATE_FILE), &state)?;
+        parsed.read_positions_changed = false;
+
+        return Ok(());
+    }
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, std::io::Error> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(path)?;
+    return Ok(Some(serde_json::from_str(&contents)?));
+}
+
+/// Writes to a temporary file first, so a crash part way through leaves the old file intact.
+fn write_json_atomically<T: Serialize>(path: &Path, value: &T) -> Result<(), std::io::Error> {
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, serde_json::to_vec(value)?)?;
+    fs::rename(&temp_path, path)?;
+    return Ok(());
+}
+
+/// A hash that won't change between builds, unlike the standard library's.
+fn fnv1a(string: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in string.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return hash;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+    use std::path::Path;
+
+    use super::Archive;
+
+    fn temp_archive_root(name: &str) -> std::path::PathBuf {
+        let root = std::env::temp_dir().join(format!(
+            "chat-tracker-archive-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+        return root;
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        let root = temp_archive_root("round-trip");
+        let archive = Archive::for_chat_log(&root, Path::new("chat.txt"));
+
+        let mut log = "===== 2024/01/06 =====
+[16:05:04] Someone tells ye, \"2 for spades\"
+[02:01:19] Mean Shad has grappled Shifty Shiner. A melee breaks out between the crews!
+[01:50:54] Bob delivers an overwhelming barrage against Petty Robert, causing some treasure to fall from their grip
+"
+        .to_string();
+        let mut parsed = archive.load().unwrap();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        parsed.mark_read("tell", Some(parsed.tells[0].id));
+        archive.save(&mut parsed).unwrap();
+        assert!(!parsed.read_positions_changed);
+
+        let mut restored = archive.load().unwrap();
+        assert_eq!(restored.tells, parsed.tells);
+        assert_eq!(restored.last_read("tell"), Some(parsed.tells[0].id));
+        assert_eq!(restored.last_line_read, 4);
+        assert_eq!(restored.battles.len(), 1);
+        assert_eq!(*restored.battles[0].greedies.get("Bob").unwrap(), 1);
+
+        // Only the new line gets parsed, and the battle carries on where it was
+        log += "[01:50:58] Bob delivers an overwhelming barrage against Petty Robert, causing some treasure to fall from their grip\n";
+        log += "[16:06:00] Someone tells ye, \"3 for spades\"\n";
+        restored.parse_chat_log(BufReader::new(log.as_bytes()));
+        archive.save(&mut restored).unwrap();
+
+        let restored = archive.load().unwrap();
+        assert_eq!(restored.tells.len(), 2);
+        assert_eq!(
+            restored.tells[1].contents,
+            "[16:06:00] Someone tells ye, \"3 for spades\""
+        );
+        assert_eq!(*restored.battles[0].greedies.get("Bob").unwrap(), 2);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_archive_keeps_history_when_log_is_cleared() {
+        let root = temp_archive_root("cleared");
+        let archive = Archive::for_chat_log(&root, Path::new("chat.txt"));
+
+        let old_log = "[16:05:04] Someone tells ye, \"2 for spades\"\n";
+        let mut parsed = archive.load().unwrap();
+        parsed.parse_chat_log(BufReader::new(old_log.as_bytes()));
+        archive.save(&mut parsed).unwrap();
+
+        let new_log = "[09:00:00] Someone tells ye, \"morning\"\n";
+        let mut parsed = archive.load().unwrap();
+        parsed.parse_chat_log(BufReader::new(new_log.as_bytes()));
+        archive.save(&mut parsed).unwrap();
+
+        let restored = archive.load().unwrap();
+        assert_eq!(restored.tells.len(), 2);
+        assert!(restored.tells[1].id > restored.tells[0].id);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -1,14 +1,33 @@
 use std::{
-    collections::{BTreeMap, VecDeque},
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet, VecDeque},
+    hash::{Hash, Hasher},
     io::{BufRead, BufReader, Read},
 };
 
 use regex::{Captures, Regex};
 use time::{macros::format_description, Date, Time};
 
-use crate::{Battle, Message};
+use crate::search_index::{word_ranges, words, MessageRef, SearchIndex};
+use crate::search_query::{Query, SearchOptions, SearchResult};
+use crate::{Battle, ChatType, Message};
+
+/// How often a parse reports how far it's got
+const PROGRESS_LINES: usize = 10_000;
+/// How many lines with something on them are looked at to tell whether a file is a chat log
+const CHAT_LOG_CHECK_LINES: usize = 20;
+
+/// What a parse found, to pass on without copying the whole chat log. The web build parses
+/// in the frontend, so has nothing to pass on.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+#[derive(Debug, Default)]
+pub struct ChatLogUpdate {
+    /// New messages, in order of creation
+    pub messages: Vec<Message>,
+    /// New battles and ones with more greedy hits, oldest first
+    pub battles: Vec<Battle>,
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 // TODO: Feels a bit weird that we can create a 'parsed chat log' without actually parsing
 // anything. Probably naming issue of parser vs parsed
 pub struct ParsedChatLog {
@@ -22,6 +41,31 @@ pub struct ParsedChatLog {
     // NOTE: Saying this is optional for now. Haven't thought enough about it
     pub current_date: Option<Date>,
     pub in_battle: bool,
+    /// Which of the battles greedy hits are being counted for, while in a battle
+    current_battle: usize,
+    /// Greedy hits read for the current battle since its start was read, by pirate. A hit's only
+    /// counted once more have been read than the battle has. None when carrying on from the
+    /// archive part way through a battle, where every hit counted has been read.
+    hits_read: Option<BTreeMap<String, u32>>,
+    /// Used to spot the chat log being cleared or replaced by a different one.
+    pub first_line: Option<String>,
+    /// How many messages we've got with each identity, so lines read a second time (after the
+    /// log is replaced, or a reload) don't get added again.
+    seen: HashMap<u64, u32>,
+    /// How many lines with each identity have been read since the log was last read from the
+    /// top. A line is only new once it's been read more times than we've got messages for it, so
+    /// the same thing said twice in a second is kept twice.
+    read_since_top: HashMap<u64, u32>,
+    /// Battles started or given greedy hits since the last update was taken, by identity
+    changed_battles: HashSet<u64>,
+    /// Newest message written to the archive, if there is one. The web build has no archive.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub last_archived_id: Option<u32>,
+    /// Newest message read in each tab, by the tab's key
+    pub read_positions: BTreeMap<String, u32>,
+    /// Read positions have changed since they were last archived
+    pub read_positions_changed: bool,
+    search_index: SearchIndex,
 }
 
 impl ParsedChatLog {
@@ -36,7 +80,111 @@ impl ParsedChatLog {
             total_lines_read: 0,
             current_date: None,
             in_battle: false,
+            current_battle: 0,
+            hits_read: None,
+            first_line: None,
+            seen: HashMap::new(),
+            read_since_top: HashMap::new(),
+            changed_battles: HashSet::new(),
+            last_archived_id: None,
+            read_positions: BTreeMap::new(),
+            read_positions_changed: false,
+            search_index: SearchIndex::new(),
+        };
+    }
+
+    /// Reads the chat log from the top again on the next parse, keeping everything we've already
+    /// got. Message ids keep counting up from where they were.
+    pub fn restart_from_beginning(&mut self) {
+        self.last_line_read = 0;
+        self.current_date = None;
+        self.in_battle = false;
+        self.hits_read = None;
+        self.first_line = None;
+        self.read_since_top.clear();
+    }
+
+    /// Adds a message to its chat, counting it as read from the log already. For messages from
+    /// the archive, which the web build doesn't have.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub fn push_message(&mut self, message: Message) {
+        *self.read_since_top.entry(message.identity()).or_default() += 1;
+        self.add_message(message);
+    }
+
+    /// Adds a message read from the log, unless it's one we've already got.
+    fn read_message(&mut self, message: Message) {
+        let identity = message.identity();
+        let read = self.read_since_top.entry(identity).or_default();
+        *read += 1;
+        if *read <= self.seen.get(&identity).copied().unwrap_or_default() {
+            return;
+        }
+        self.add_message(message);
+    }
+
+    fn add_message(&mut self, message: Message) {
+        *self.seen.entry(message.identity()).or_default() += 1;
+        let messages = match message.chat_type {
+            ChatType::Chat => &mut self.chat_messages,
+            ChatType::Trade => &mut self.trade_chat_messages,
+            ChatType::Global => &mut self.global_chat_messages,
+            ChatType::Tell => &mut self.tells,
+            ChatType::All => panic!("Messages always belong to a single chat"),
+        };
+        let message_ref = MessageRef {
+            id: message.id,
+            chat_type: message.chat_type,
+            index: messages.len(),
         };
+        self.search_index.insert(&message.contents, message_ref);
+        messages.push(message);
+    }
+
+    /// Adds a battle from the archive, newest first. Returns false if we already had it.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub fn push_battle(&mut self, battle: Battle) -> bool {
+        let identity = battle.identity();
+        if self
+            .battles
+            .iter()
+            .any(|battle| battle.identity() == identity)
+        {
+            return false;
+        }
+        self.battles.push_front(battle);
+        return true;
+    }
+
+    /// Everything new since the last update was taken, along with messages after `after_id`, so
+    /// a copy of this chat log can be brought up to date with `apply_update`.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub fn take_update(&mut self, after_id: Option<u32>) -> ChatLogUpdate {
+        let messages = self.messages_after(after_id).into_iter().cloned().collect();
+        let changed_battles = std::mem::take(&mut self.changed_battles);
+        let battles = self
+            .battles
+            .iter()
+            .rev()
+            .filter(|battle| changed_battles.contains(&battle.identity()))
+            .cloned()
+            .collect();
+        return ChatLogUpdate { messages, battles };
+    }
+
+    /// Adds what's new from another copy of this chat log, taken with `take_update`.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub fn apply_update(&mut self, update: ChatLogUpdate) {
+        for message in update.messages {
+            self.add_message(message);
+        }
+        for battle in update.battles {
+            let identity = battle.identity();
+            match self.battles.iter_mut().find(|b| b.identity() == identity) {
+                Some(existing) => *existing = battle,
+                None => self.battles.push_front(battle),
+            }
+        }
     }
 
     pub fn messages_in_order_of_creation(&self) -> Vec<&Message> {
@@ -63,62 +211,245 @@ impl ParsedChatLog {
         return messages;
     }
 
+    /// Messages from a single chat, or every chat for `ChatType::All`, in order of creation.
+    pub fn messages_of_type(&self, chat_type: ChatType) -> Vec<&Message> {
+        let messages = match chat_type {
+            ChatType::Chat => &self.chat_messages,
+            ChatType::Trade => &self.trade_chat_messages,
+            ChatType::Global => &self.global_chat_messages,
+            ChatType::Tell => &self.tells,
+            ChatType::All => return self.messages_in_order_of_creation(),
+        };
+        return messages.iter().collect();
+    }
+
+    fn messages_in_chat(&self, chat_type: ChatType) -> Vec<&Vec<Message>> {
+        return match chat_type {
+            ChatType::Chat => vec![&self.chat_messages],
+            ChatType::Trade => vec![&self.trade_chat_messages],
+            ChatType::Global => vec![&self.global_chat_messages],
+            ChatType::Tell => vec![&self.tells],
+            ChatType::All => vec![
+                &self.chat_messages,
+                &self.trade_chat_messages,
+                &self.global_chat_messages,
+                &self.tells,
+            ],
+        };
+    }
+
+    pub fn newest_message_id(&self, chat_type: ChatType) -> Option<u32> {
+        return self
+            .messages_in_chat(chat_type)
+            .iter()
+            .filter_map(|messages| messages.last())
+            .map(|message| message.id)
+            .max();
+    }
+
+    /// How many messages in a chat are newer than `last_read`, not counting hidden ones.
+    pub fn unread_count(
+        &self,
+        chat_type: ChatType,
+        last_read: Option<u32>,
+        is_hidden: impl Fn(&Message) -> bool,
+    ) -> usize {
+        return self
+            .messages_in_chat(chat_type)
+            .iter()
+            .map(|messages| {
+                // Each chat is in id order
+                let first_unread =
+                    messages.partition_point(|message| Some(message.id) <= last_read);
+                messages[first_unread..]
+                    .iter()
+                    .filter(|message| !is_hidden(message))
+                    .count()
+            })
+            .sum();
+    }
+
+    pub fn last_read(&self, tab_key: &str) -> Option<u32> {
+        return self.read_positions.get(tab_key).copied();
+    }
+
+    /// Marks everything up to `newest_id` as read in a tab. Does nothing if there's nothing to
+    /// read yet.
+    pub fn mark_read(&mut self, tab_key: &str, newest_id: Option<u32>) {
+        let newest_id = match newest_id {
+            Some(newest_id) => newest_id,
+            None => return,
+        };
+        if self.last_read(tab_key) != Some(newest_id) {
+            self.read_positions.insert(tab_key.to_string(), newest_id);
+            self.read_positions_changed = true;
+        }
+    }
+
+    /// Messages with an id after `id`, in order of creation. Everything if no id is given.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub fn messages_after(&self, id: Option<u32>) -> Vec<&Message> {
+        let id = match id {
+            Some(id) => id,
+            None => return self.messages_in_order_of_creation(),
+        };
+        let mut messages = vec![];
+        for chat in [
+            &self.chat_messages,
+            &self.global_chat_messages,
+            &self.trade_chat_messages,
+            &self.tells,
+        ] {
+            // Each chat is already in id order, so we only need to find where the new ones start
+            let first_new = chat.partition_point(|message| message.id <= id);
+            messages.extend(&chat[first_new..]);
+        }
+
+        messages.sort_by_key(|message| message.id);
+        return messages;
+    }
+
+    /// Messages containing the search term, case insensitive, the same as `--search` on the
+    /// command line. The search index narrows down which messages need checking, so it's quick
+    /// however many messages there are.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
     pub fn messages_containing_search_term(&self, search_string: &str) -> Vec<&Message> {
-        // SPEEDUP: Cache the matching messages result so this doesn't trigger every time the UI
-        // updates in the search term tab
-        let total_message_count = self.chat_messages.len()
-            + self.global_chat_messages.len()
-            + self.trade_chat_messages.len()
-            + self.tells.len();
-        let mut messages = Vec::with_capacity(total_message_count);
-        if !search_string.is_empty() {
-            for msg in &self.chat_messages {
-                if msg
-                    .contents
-                    .to_lowercase()
-                    .contains(&search_string.to_lowercase())
-                {
-                    messages.push(msg);
-                }
-            }
-            for msg in &self.trade_chat_messages {
-                if msg
+        if search_string.trim().is_empty() {
+            return vec![];
+        }
+
+        let search_string_lowercase = search_string.to_lowercase();
+        return self
+            .indexed_messages(self.search_index.search_anywhere(search_string))
+            .into_iter()
+            .filter(|message| {
+                message
                     .contents
                     .to_lowercase()
-                    .contains(&search_string.to_lowercase())
-                {
-                    messages.push(msg);
+                    .contains(&search_string_lowercase)
+            })
+            .collect();
+    }
+
+    /// The messages found in the search index, in order of creation. Nothing to look up in the
+    /// index (just punctuation, like "?") means every message.
+    fn indexed_messages(&self, matches: Option<Vec<MessageRef>>) -> Vec<&Message> {
+        return match matches {
+            Some(matches) => matches
+                .iter()
+                .map(|message_ref| self.message(message_ref))
+                .collect(),
+            None => self.messages_in_order_of_creation(),
+        };
+    }
+
+    /// Messages matching a search query, in order of creation. The search index narrows things
+    /// down first when the query has words every match needs.
+    pub fn messages_matching_query(&self, query: &Query, options: &SearchOptions) -> Vec<&Message> {
+        let required_words = query.required_words();
+        let candidates = self.indexed_messages(self.search_index.search(&required_words.join(" ")));
+        return candidates
+            .into_iter()
+            .filter(|message| query.matches(message, options))
+            .collect();
+    }
+
+    /// Messages with a word spelt roughly like each word of the search term, fewest typos first
+    /// then newest first. The close enough words are highlighted.
+    pub fn messages_fuzzy_matching(&self, search_string: &str) -> Vec<SearchResult<'_>> {
+        let search_words = words(search_string);
+        if search_words.is_empty() {
+            return vec![];
+        }
+
+        // For each message, how many search words it has something close to, and how many typos
+        // away those were in total
+        let mut message_distances: HashMap<MessageRef, (usize, usize)> = HashMap::new();
+        let mut close_words: HashSet<&str> = HashSet::new();
+        for search_word in &search_words {
+            let mut closest: HashMap<MessageRef, usize> = HashMap::new();
+            for (word, distance, messages) in self.search_index.similar_words(search_word) {
+                close_words.insert(word);
+                for message_ref in messages {
+                    let closest_distance = closest.entry(*message_ref).or_insert(distance);
+                    *closest_distance = distance.min(*closest_distance);
                 }
             }
-            for msg in &self.global_chat_messages {
-                if msg
-                    .contents
-                    .to_lowercase()
-                    .contains(&search_string.to_lowercase())
-                {
-                    messages.push(msg);
-                }
+            for (message_ref, distance) in closest {
+                let (words_matched, total_distance) =
+                    message_distances.entry(message_ref).or_default();
+                *words_matched += 1;
+                *total_distance += distance;
             }
-            for msg in &self.tells {
-                if msg
-                    .contents
-                    .to_lowercase()
-                    .contains(&search_string.to_lowercase())
-                {
-                    messages.push(msg);
+        }
+
+        let mut matches: Vec<(MessageRef, usize)> = message_distances
+            .into_iter()
+            .filter(|(_, (words_matched, _))| *words_matched == search_words.len())
+            .map(|(message_ref, (_, total_distance))| (message_ref, total_distance))
+            .collect();
+        matches.sort_by_key(|(message_ref, total_distance)| {
+            (*total_distance, std::cmp::Reverse(*message_ref))
+        });
+
+        return matches
+            .into_iter()
+            .map(|(message_ref, _)| {
+                let message = self.message(&message_ref);
+                let highlights = word_ranges(&message.contents)
+                    .into_iter()
+                    .filter(|range| {
+                        close_words
+                            .contains(message.contents[range.clone()].to_lowercase().as_str())
+                    })
+                    .collect();
+                SearchResult {
+                    message,
+                    highlights,
                 }
+            })
+            .collect();
+    }
+
+    pub fn message(&self, message_ref: &MessageRef) -> &Message {
+        let messages = match message_ref.chat_type {
+            ChatType::Chat => &self.chat_messages,
+            ChatType::Trade => &self.trade_chat_messages,
+            ChatType::Global => &self.global_chat_messages,
+            ChatType::Tell => &self.tells,
+            ChatType::All => panic!("Messages always belong to a single chat"),
+        };
+        return &messages[message_ref.index];
+    }
+
+    /// The message with this id, if we've got it.
+    pub fn message_with_id(&self, id: u32) -> Option<&Message> {
+        for messages in self.messages_in_chat(ChatType::All) {
+            // Each chat is in id order
+            if let Ok(i) = messages.binary_search_by_key(&id, |message| message.id) {
+                return Some(&messages[i]);
             }
         }
+        return None;
+    }
 
-        messages.sort_by(|a, b| a.id.cmp(&b.id));
-        return messages;
+    /// Parses anything new in the chat log. Returns false if the log's shorter than what's been
+    /// read of it, it's been cut short or replaced. Nothing's read then, it needs parsing again
+    /// from the top.
+    pub fn parse_chat_log<R: Read>(&mut self, buf_reader: BufReader<R>) -> bool {
+        return self.parse_chat_log_with_progress(buf_reader, |_| {});
     }
 
-    pub fn parse_chat_log<R: Read>(&mut self, buf_reader: BufReader<R>) {
+    /// Parses like `parse_chat_log`, calling `on_progress` with how many new lines have been read
+    /// every PROGRESS_LINES lines, so a long parse can be followed.
+    pub fn parse_chat_log_with_progress<R: Read>(
+        &mut self,
+        buf_reader: BufReader<R>,
+        mut on_progress: impl FnMut(usize),
+    ) -> bool {
         // TODO: NOTE: We don't have to go through the entire file again, just what has changed?
         // TODO: Add some configurable limit of how many lines to look back on.
         let lines = buf_reader.lines();
-        let mut battle_count = 0;
 
         let timestamp_regex = r"\[(\d\d:\d\d:\d\d)\]".to_string();
         let sender_section_for_regex = r" (\w+( |-*)?\w+)".to_string();
@@ -131,12 +462,35 @@ impl ParsedChatLog {
         let date_seperator_regex = Regex::new(r"={5} (\d\d\d\d/\d\d/\d\d) ={5}").unwrap();
         let date_format = format_description!("[year]/[month]/[day]");
 
-        let starting_line = self.last_line_read;
-        // FIXME(?): TODO: Assuming the chat log will never be pruned or truncated in some way whilst the parser is running. Otherwise our starting line could be beyond what the file's actual size is now. We could warn the user, if we kept track of how many lines we've seen in this parse attempt (couldn't just skip x lines any more, we'd need to iterate through everything and sum it up, but might not actually matter performance wise to count per line), and compare it to total_lines_read (if < warn user)
+        let mut starting_line = self.last_line_read;
+        if starting_line == 0 {
+            self.read_since_top.clear();
+        }
+        let mut lines_seen = 0;
+        let mut next_progress = PROGRESS_LINES;
+
+        for line in lines {
+            lines_seen += 1;
+            if lines_seen == 1 {
+                let first_line = line.as_ref().ok().cloned();
+                if self.first_line.is_some() && first_line != self.first_line {
+                    // Not the log we were reading before, it's been cleared or swapped out. Read
+                    // it all again, anything we've already got gets skipped over.
+                    self.restart_from_beginning();
+                    starting_line = 0;
+                }
+                self.first_line = first_line;
+            }
+            if lines_seen <= starting_line {
+                continue;
+            }
 
-        for line in lines.skip(starting_line) {
             self.last_line_read += 1;
             self.total_lines_read += 1;
+            if lines_seen - starting_line == next_progress {
+                on_progress(next_progress);
+                next_progress += PROGRESS_LINES;
+            }
 
             // TODO: FIXME: BUG: Message id will increase if a message is multi line (I think), but it still increments so for ordering it works.
             let message_id = self.total_lines_read as u32;
@@ -148,22 +502,22 @@ impl ParsedChatLog {
 
             if let Some(captures) = date_seperator_regex.captures(&line) {
                 let date = &captures[1];
-                let date = Date::parse(date, &date_format).unwrap();
-                self.current_date = Some(date);
+                // Anything that isn't a real date is left out rather than stopping the parse
+                self.current_date = Date::parse(date, &date_format).ok();
             }
 
             // TODO: FIXME: It may be possible for a chat message to span multiple lines
             //      a chat from a player will end in a ", even if it's over multiple lines
             if let Some(mut message) = is_chat_line(&line, &chat_line_regex, message_id) {
                 message.date = self.current_date;
-                self.chat_messages.push(message);
+                self.read_message(message);
                 continue;
             }
 
             if let Some(mut message) = is_trade_chat_line(&line, &trade_chat_line_regex, message_id)
             {
                 message.date = self.current_date;
-                self.trade_chat_messages.push(message);
+                self.read_message(message);
                 continue;
             }
 
@@ -171,13 +525,13 @@ impl ParsedChatLog {
                 is_global_chat_line(&line, &global_chat_line_regex, message_id)
             {
                 message.date = self.current_date;
-                self.global_chat_messages.push(message);
+                self.read_message(message);
                 continue;
             }
 
             if let Some(mut message) = is_tell_chat_line(&line, &tell_chat_line_regex, message_id) {
                 message.date = self.current_date;
-                self.tells.push(message);
+                self.read_message(message);
                 continue;
             }
 
@@ -186,29 +540,47 @@ impl ParsedChatLog {
                 // TODO: Would like ship/battle naming to be better, but it works
                 let attacker_ship = splits[1].to_string() + " " + splits[2];
                 let defender_ship = splits[5].to_string() + " " + splits[6];
-                self.in_battle = true;
-                battle_count += 1;
                 let battle = Battle {
-                    _id: battle_count,
+                    id: self.battles.len() as u32 + 1,
                     greedies: BTreeMap::new(),
                     defender_ship,
                     attacker_ship,
+                    date: self.current_date,
+                    started_at: line.get(1..9).and_then(get_time_from_timestamp),
+                };
+                let identity = battle.identity();
+                // A battle read again carries on where it was, so its hits aren't counted twice
+                self.current_battle = match self
+                    .battles
+                    .iter()
+                    .position(|battle| battle.identity() == identity)
+                {
+                    Some(current_battle) => current_battle,
+                    None => {
+                        self.battles.push_front(battle);
+                        0
+                    }
                 };
-                self.battles.push_front(battle);
+                self.changed_battles.insert(identity);
+                self.in_battle = true;
+                self.hits_read = Some(BTreeMap::new());
                 continue;
             }
 
             if self.in_battle && is_a_greedy_line(&line) {
                 let splits: Vec<&str> = line.split(' ').collect();
                 let pirate_name = splits[1];
-                let battle: &mut Battle = self.battles.front_mut().unwrap();
-                let greedies = &mut battle.greedies;
-
-                *greedies.entry(pirate_name.to_string()).or_default() += 1;
-            }
-
-            if !self.in_battle && is_a_greedy_line(&line) {
-                dbg!("Processing greedy line, but program believes we're outside of battle!");
+                let battle: &mut Battle = &mut self.battles[self.current_battle];
+                let hits_read = self
+                    .hits_read
+                    .get_or_insert_with(|| battle.greedies.clone());
+                let read = hits_read.entry(pirate_name.to_string()).or_default();
+                *read += 1;
+                let counted = battle.greedies.entry(pirate_name.to_string()).or_default();
+                if *read > *counted {
+                    *counted += 1;
+                    self.changed_battles.insert(battle.identity());
+                }
             }
 
             if is_battle_ended_line(&line) {
@@ -216,10 +588,25 @@ impl ParsedChatLog {
             }
         }
 
+        if lines_seen < starting_line {
+            // The log has been cut short since we last read it
+            self.restart_from_beginning();
+            return false;
+        }
+
         // TODO: FIXME: Don't just clone these messages (Or at least change their ID)
+        return true;
     }
 }
 
+/// Stays the same however many times the line is read, unlike message ids.
+pub fn line_identity(date: Option<Date>, line: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    date.hash(&mut hasher);
+    line.hash(&mut hasher);
+    return hasher.finish();
+}
+
 fn is_a_greedy_line(string: &str) -> bool {
     return string.contains("delivers a")
         || string.contains("performs a")
@@ -227,6 +614,22 @@ fn is_a_greedy_line(string: &str) -> bool {
         || string.contains("swings a");
 }
 
+/// Whether this looks like a Puzzle Pirates chat log, where lines start with a timestamp or are a
+/// date between days. An empty log is fine, the game just hasn't written to it yet.
+pub fn looks_like_chat_log<R: Read>(buf_reader: BufReader<R>) -> bool {
+    let chat_log_line_regex =
+        Regex::new(r"^(\[\d\d:\d\d:\d\d\] |={5} \d\d\d\d/\d\d/\d\d ={5})").unwrap();
+    // Split on bytes, the log can have invalid utf-8 in it
+    let lines: Vec<String> = buf_reader
+        .split(b'\n')
+        .map_while(Result::ok)
+        .map(|line| String::from_utf8_lossy(&line).to_string())
+        .filter(|line| !line.trim().is_empty())
+        .take(CHAT_LOG_CHECK_LINES)
+        .collect();
+    return lines.is_empty() || lines.iter().any(|line| chat_log_line_regex.is_match(line));
+}
+
 fn is_battle_ended_line(string: &str) -> bool {
     return string.contains("Game Over");
 }
@@ -236,27 +639,32 @@ fn is_battle_started_line(string: &str) -> bool {
 }
 
 /// Timestamp should be in the format [hour:minute:second]
-fn get_time_from_timestamp(timestamp: &str) -> Time {
+fn get_time_from_timestamp(timestamp: &str) -> Option<Time> {
     let timestamp_format = format_description!("[hour]:[minute]:[second]");
-    // TODO: Handle timestamp parse failure
-    let timestamp = time::Time::parse(timestamp, &timestamp_format).unwrap();
-    return timestamp;
+    return time::Time::parse(timestamp, &timestamp_format).ok();
 }
 
-fn message_from_captures(captures: &Captures, chat_message: &str, message_id: u32) -> Message {
+fn message_from_captures(
+    captures: &Captures,
+    chat_message: &str,
+    message_id: u32,
+    chat_type: ChatType,
+) -> Option<Message> {
     let timestamp = captures[1].to_string();
     let name = captures[2].to_string();
-    return Message::new(
+    return Some(Message::new(
         chat_message.to_string(),
         name,
-        get_time_from_timestamp(&timestamp),
+        // Not a real time, so not a chat line after all
+        get_time_from_timestamp(&timestamp)?,
         message_id,
-    );
+        chat_type,
+    ));
 }
 
 fn is_chat_line(string: &str, regex: &Regex, message_id: u32) -> Option<Message> {
     if let Some(captures) = regex.captures(string) {
-        return Some(message_from_captures(&captures, string, message_id));
+        return message_from_captures(&captures, string, message_id, ChatType::Chat);
     } else {
         return None;
     }
@@ -264,7 +672,7 @@ fn is_chat_line(string: &str, regex: &Regex, message_id: u32) -> Option<Message>
 
 fn is_trade_chat_line(string: &str, regex: &Regex, message_id: u32) -> Option<Message> {
     if let Some(captures) = regex.captures(string) {
-        return Some(message_from_captures(&captures, string, message_id));
+        return message_from_captures(&captures, string, message_id, ChatType::Trade);
     } else {
         return None;
     }
@@ -272,7 +680,7 @@ fn is_trade_chat_line(string: &str, regex: &Regex, message_id: u32) -> Option<Me
 
 fn is_global_chat_line(string: &str, regex: &Regex, message_id: u32) -> Option<Message> {
     if let Some(captures) = regex.captures(string) {
-        return Some(message_from_captures(&captures, string, message_id));
+        return message_from_captures(&captures, string, message_id, ChatType::Global);
     } else {
         return None;
     }
@@ -280,7 +688,7 @@ fn is_global_chat_line(string: &str, regex: &Regex, message_id: u32) -> Option<M
 
 fn is_tell_chat_line(string: &str, regex: &Regex, message_id: u32) -> Option<Message> {
     if let Some(captures) = regex.captures(string) {
-        return Some(message_from_captures(&captures, string, message_id));
+        return message_from_captures(&captures, string, message_id, ChatType::Tell);
     } else {
         return None;
     }
@@ -293,8 +701,10 @@ mod tests {
     use time::Month;
 
     use crate::{
-        chat_log::{is_a_greedy_line, is_battle_started_line, ParsedChatLog},
-        Message,
+        chat_log::{is_a_greedy_line, is_battle_started_line, looks_like_chat_log, ParsedChatLog},
+        mutes::Mutes,
+        search_query::{Query, SearchOptions},
+        ChatType, Message,
     };
 
     // TODO: Feels like we're testing the same thing over and over for each chat type, but they do have different regexes, so..?
@@ -501,6 +911,269 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_messages_after() {
+        let global_chat = "[16:05:04] Someone global chats, \"2 for spades\"";
+        let tell = "[16:05:04] Someone tells ye, \"2 for spades\"";
+        let trade_chat = "[16:05:05] Someone trade chats, \"Buying weavery\"";
+        let mut log = format!("{}\n{}\n", global_chat, tell);
+        let reader = BufReader::new(log.as_bytes());
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(reader);
+
+        assert_eq!(parsed.messages_after(None).len(), 2);
+        let last_seen = parsed.messages_after(None).last().unwrap().id;
+        assert!(parsed.messages_after(Some(last_seen)).is_empty());
+
+        log += trade_chat;
+        let reader = BufReader::new(log.as_bytes());
+        parsed.parse_chat_log(reader);
+        let new_messages = parsed.messages_after(Some(last_seen));
+        assert_eq!(new_messages.len(), 1);
+        assert_eq!(new_messages[0].contents, trade_chat);
+    }
+
+    #[test]
+    fn test_replaced_log_is_read_again_without_duplicates() {
+        let first_log = "===== 2024/01/06 =====
+[16:05:04] Someone tells ye, \"2 for spades\"
+[02:01:19] Mean Shad has grappled Shifty Shiner. A melee breaks out between the crews!
+[01:50:54] Bob delivers an overwhelming barrage against Petty Robert, causing some treasure to fall from their grip";
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(first_log.as_bytes()));
+        assert_eq!(parsed.tells.len(), 1);
+
+        // The log has been swapped for one that has the old one's messages in it too
+        let second_log = "===== 2024/01/07 =====
+[09:00:00] Someone tells ye, \"morning\"";
+        let rotated_log = format!("Chat log restarted\n{}\n{}", first_log, second_log);
+        parsed.parse_chat_log(BufReader::new(rotated_log.as_bytes()));
+        assert_eq!(parsed.tells.len(), 2);
+        assert_eq!(parsed.battles.len(), 1);
+        assert_eq!(*parsed.battles[0].greedies.get("Bob").unwrap(), 1);
+        assert!(parsed.tells[1].id > parsed.tells[0].id);
+    }
+
+    #[test]
+    fn test_battle_read_again_carries_on() {
+        let hit = "[01:50:54] Bob delivers an overwhelming barrage against Petty Robert, causing some treasure to fall from their grip";
+        let mut log = format!(
+            "[02:01:19] Mean Shad has grappled Shifty Shiner. A melee breaks out between the crews!\n{}\n",
+            hit
+        );
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+
+        // Reloaded part way through the battle
+        parsed.restart_from_beginning();
+        log += &format!("{}\n", hit);
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        assert_eq!(parsed.battles.len(), 1);
+        assert_eq!(*parsed.battles[0].greedies.get("Bob").unwrap(), 2);
+        log += &format!("{}\n", hit);
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        assert_eq!(*parsed.battles[0].greedies.get("Bob").unwrap(), 3);
+    }
+
+    #[test]
+    fn test_chat_log_update() {
+        let hit = "[01:50:54] Bob delivers an overwhelming barrage against Petty Robert, causing some treasure to fall from their grip";
+        let mut log = format!(
+            "[16:05:04] Someone tells ye, \"2 for spades\"\n[02:01:19] Mean Shad has grappled Shifty Shiner. A melee breaks out between the crews!\n{}\n",
+            hit
+        );
+        let mut parsed = ParsedChatLog::new();
+        let mut copy = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        copy.apply_update(parsed.take_update(None));
+        assert_eq!(copy.tells.len(), 1);
+        assert_eq!(*copy.battles[0].greedies.get("Bob").unwrap(), 1);
+
+        // Only what's new is passed on, and a battle with more hits replaces the one we had
+        log += &format!("{}\n[16:05:09] Someone tells ye, \"3 for spades\"\n", hit);
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        let update = parsed.take_update(copy.newest_message_id(ChatType::All));
+        assert_eq!(update.messages.len(), 1);
+        copy.apply_update(update);
+        assert_eq!(copy.tells.len(), 2);
+        assert_eq!(copy.battles.len(), 1);
+        assert_eq!(*copy.battles[0].greedies.get("Bob").unwrap(), 2);
+        assert_eq!(copy.messages_containing_search_term("3 for").len(), 1);
+
+        let update = parsed.take_update(copy.newest_message_id(ChatType::All));
+        assert!(update.messages.is_empty());
+        assert!(update.battles.is_empty());
+    }
+
+    #[test]
+    fn test_repeated_lines_are_kept() {
+        let ahoy = "[16:05:04] Bob says, \"ahoy\"";
+        let log = format!("{}\n{}\n", ahoy, ahoy);
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        assert_eq!(parsed.chat_messages.len(), 2);
+
+        // Read again from the top, only the line said a third time is new
+        parsed.restart_from_beginning();
+        let log = format!("{}{}\n", log, ahoy);
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        assert_eq!(parsed.chat_messages.len(), 3);
+        parsed.restart_from_beginning();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        assert_eq!(parsed.chat_messages.len(), 3);
+    }
+
+    #[test]
+    fn test_truncated_log_is_read_from_the_top() {
+        let tell = "[16:05:04] Someone tells ye, \"2 for spades\"";
+        let log = format!("{}\n{}\n{}", tell, tell, tell);
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        assert_eq!(parsed.last_line_read, 3);
+
+        let other_tell = "[16:05:09] Someone tells ye, \"3 for spades\"";
+        let log = format!("{}\n{}", tell, other_tell);
+        assert!(!parsed.parse_chat_log(BufReader::new(log.as_bytes())));
+        assert_eq!(parsed.last_line_read, 0);
+        assert!(parsed.parse_chat_log(BufReader::new(log.as_bytes())));
+        assert_eq!(parsed.last_line_read, 2);
+        assert_eq!(parsed.tells.last().unwrap().contents, other_tell);
+    }
+
+    #[test]
+    fn test_messages_containing_search_term() {
+        let log = "[16:05:04] Someone trade chats, \"? Buying weavery or plot on barb\"
+[16:05:05] Big Barry global chats, \"2 for spades\"
+[16:05:06] Someone tells ye, \"Got any WEAVERY deeds?\"";
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+
+        let matches = parsed.messages_containing_search_term("weav");
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].sender, "Someone");
+        assert_eq!(matches[1].chat_type, crate::ChatType::Tell);
+
+        assert_eq!(parsed.messages_containing_search_term("eaver").len(), 2);
+        assert_eq!(parsed.messages_containing_search_term("for spa").len(), 1);
+        assert!(parsed
+            .messages_containing_search_term("barry spades")
+            .is_empty());
+        assert_eq!(parsed.messages_containing_search_term("?").len(), 2);
+        assert!(parsed.messages_containing_search_term("  ").is_empty());
+        assert!(parsed.messages_containing_search_term("selling").is_empty());
+    }
+
+    #[test]
+    fn test_messages_matching_query() {
+        let log = "[16:05:04] Bob trade chats, \"Buying iron\"
+[16:05:05] Bob trade chats, \"Selling iron\"
+[16:05:06] Bob global chats, \"Buying iron too\"
+[16:05:07] Barry trade chats, \"Buying wood\"";
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+
+        let query = Query::parse("from:bob in:trade iron -selling")
+            .unwrap()
+            .unwrap();
+        let matches = parsed.messages_matching_query(&query, &SearchOptions::default());
+        assert_eq!(matches.len(), 1);
+        assert_eq!(
+            matches[0].contents,
+            "[16:05:04] Bob trade chats, \"Buying iron\""
+        );
+
+        // No words to look up, so every message is checked
+        let query = Query::parse("in:trade -from:Bob").unwrap().unwrap();
+        assert_eq!(
+            parsed.messages_matching_query(&query, &SearchOptions::default())[0].sender,
+            "Barry"
+        );
+    }
+
+    #[test]
+    fn test_messages_fuzzy_matching() {
+        let log = "[16:05:04] Bob trade chats, \"Buying weavry on barb\"
+[16:05:05] Bob trade chats, \"Selling weaverie\"
+[16:05:06] Bob trade chats, \"Weavery for sale\"
+[16:05:07] Bob trade chats, \"Selling wood\"";
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+
+        let results = parsed.messages_fuzzy_matching("weavery");
+        let contents: Vec<&str> = results
+            .iter()
+            .map(|result| result.message.contents.as_str())
+            .collect();
+        assert_eq!(
+            contents,
+            vec![
+                "[16:05:06] Bob trade chats, \"Weavery for sale\"",
+                "[16:05:04] Bob trade chats, \"Buying weavry on barb\"",
+                "[16:05:05] Bob trade chats, \"Selling weaverie\""
+            ]
+        );
+        assert_eq!(results[1].highlights, vec![36..42]);
+
+        // Every word has to be close to something
+        assert_eq!(parsed.messages_fuzzy_matching("seling weavery").len(), 1);
+        assert!(parsed.messages_fuzzy_matching("?").is_empty());
+    }
+
+    #[test]
+    fn test_unread_counts() {
+        let log = "[16:05:04] Bob trade chats, \"Buying iron\"
+[16:05:05] Bob tells ye, \"Hello\"
+[16:05:06] Bob trade chats, \"Selling iron\"";
+        let mut parsed = ParsedChatLog::new();
+        assert_eq!(parsed.newest_message_id(crate::ChatType::All), None);
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+
+        let first_trade = parsed.trade_chat_messages[0].id;
+        let shown = |_: &Message| false;
+        assert_eq!(parsed.unread_count(crate::ChatType::Trade, None, shown), 2);
+        assert_eq!(
+            parsed.unread_count(crate::ChatType::Trade, Some(first_trade), shown),
+            1
+        );
+        assert_eq!(
+            parsed.unread_count(crate::ChatType::All, Some(first_trade), shown),
+            2
+        );
+        let mutes = Mutes::new(&[], &["selling".to_string()]);
+        let hidden = |message: &Message| mutes.is_muted(message);
+        assert_eq!(parsed.unread_count(crate::ChatType::Trade, None, hidden), 1);
+
+        parsed.mark_read("trade", Some(first_trade));
+        assert!(parsed.read_positions_changed);
+        assert_eq!(parsed.last_read("trade"), Some(first_trade));
+        assert_eq!(
+            parsed.newest_message_id(crate::ChatType::All),
+            Some(parsed.trade_chat_messages[1].id)
+        );
+    }
+
     // TODO: Some tests that check non matching lines too
-    // TODO: Filter test as well
+
+    #[test]
+    fn test_looks_like_chat_log() {
+        let looks_like = |log: &str| looks_like_chat_log(BufReader::new(log.as_bytes()));
+        assert!(looks_like(""));
+        assert!(looks_like("===== 2024/01/06 =====\n"));
+        assert!(looks_like(
+            "\n[16:05:04] Someone tells ye, \"2 for spades\"\n"
+        ));
+        assert!(!looks_like("[package]\nname = \"chat_tracker\"\n"));
+        assert!(!looks_like(&"Just some notes\n".repeat(5)));
+    }
+
+    #[test]
+    fn test_bad_times_and_dates() {
+        let log = "===== 2024/13/45 =====
+[16:05:04] Bob says, \"ahoy\"
+[99:99:99] Bob says, \"ahoy\"";
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        assert_eq!(parsed.chat_messages.len(), 1);
+        assert_eq!(parsed.chat_messages[0].date, None);
+    }
 }
@@ -0,0 +1,750 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde_json::json;
+use time::macros::format_description;
+use time::{Date, Time};
+
+use crate::chat_log::ParsedChatLog;
+use crate::tui;
+use crate::{Battle, ChatType, Message};
+
+/// Any quicker and following would just spin re-reading the chat log
+const MIN_FOLLOW_INTERVAL_MS: u64 = 100;
+
+const USAGE: &str = "Usage:
+  tracker [--config <path>]                 Start the chat tracker window
+  tracker export [options]
+  tracker follow [options]
+  tracker tui [--log <path>] [--config <path>]
+
+Config options, for the window and tui:
+  --config <path>         Config file to use (default: config.toml in the
+                          puzzle-pirates-chat-tracker folder of your config folder)
+
+Export options:
+  --log <path>            Chat log to parse (required)
+  --format <json|csv>     Output format, JSON Lines or CSV (default: json)
+  --kind <kind>           messages, battles, greedies or all (default: all, json only)
+
+Follow options:
+  --log <path>            Chat log to follow (required)
+  --format <text|json>    Output format, plain text or JSON Lines (default: text)
+  --interval <ms>         How often to check for new lines, at least 100 (default: 2000)
+  --from-start            Print the messages already in the chat log before following
+
+Tui options:
+  --log <path>            Chat log to show (default: the chat log picked in the window)
+
+Message filters, for both export and follow:
+  --channel <channel>     Only messages from chat, trade, global, tell or all (default: all)
+  --sender <name>         Only messages sent by this pirate or NPC
+  --search <term>         Only messages containing this search term
+  --since <YYYY-MM-DD>    Only messages and battles on or after this date";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Export(ExportOptions),
+    Follow(FollowOptions),
+    Tui(Option<PathBuf>),
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+struct ExportOptions {
+    log: PathBuf,
+    output: ExportOutput,
+    filter: MessageFilter,
+}
+
+#[derive(Debug, PartialEq)]
+struct FollowOptions {
+    log: PathBuf,
+    format: FollowFormat,
+    interval: Duration,
+    from_start: bool,
+    filter: MessageFilter,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum ExportFormat {
+    JsonLines,
+    Csv,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum FollowFormat {
+    Text,
+    JsonLines,
+}
+
+/// What gets exported and how. CSV has one table, so it's one kind at a time.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum ExportOutput {
+    /// Everything if no kind is given
+    JsonLines(Option<ExportKind>),
+    Csv(ExportKind),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum ExportKind {
+    Messages,
+    Battles,
+    Greedies,
+}
+
+#[derive(Debug, PartialEq)]
+struct MessageFilter {
+    channel: ChatType,
+    sender: Option<String>,
+    search: Option<String>,
+    since: Option<Date>,
+}
+
+impl Default for MessageFilter {
+    fn default() -> Self {
+        return MessageFilter {
+            channel: ChatType::All,
+            sender: None,
+            search: None,
+            since: None,
+        };
+    }
+}
+
+impl MessageFilter {
+    fn matches(&self, message: &Message) -> bool {
+        if self.channel != ChatType::All && message.chat_type != self.channel {
+            return false;
+        }
+        if let Some(sender) = &self.sender {
+            if !message.sender.eq_ignore_ascii_case(sender) {
+                return false;
+            }
+        }
+        if let Some(search) = &self.search {
+            if !message
+                .contents
+                .to_lowercase()
+                .contains(&search.to_lowercase())
+            {
+                return false;
+            }
+        }
+        return is_on_or_after(message.date, self.since);
+    }
+
+    /// Handles the filter options shared between commands. Returns false if `arg` isn't one of
+    /// them.
+    fn parse_option(
+        &mut self,
+        arg: &str,
+        value: &mut dyn FnMut() -> Result<String, String>,
+    ) -> Result<bool, String> {
+        match arg {
+            "--channel" => {
+                let key = value()?;
+                self.channel =
+                    ChatType::from_key(&key).ok_or_else(|| format!("Unknown channel '{}'", key))?;
+            }
+            "--sender" => self.sender = Some(value()?),
+            "--search" => self.search = Some(value()?),
+            "--since" => self.since = Some(parse_date(&value()?)?),
+            _ => return Ok(false),
+        }
+        return Ok(true);
+    }
+}
+
+/// Takes `--config <path>` out of `args` wherever it is, since it goes with any command.
+pub fn take_config_option(args: &mut Vec<String>) -> Result<Option<PathBuf>, String> {
+    let i = match args.iter().position(|arg| arg == "--config") {
+        Some(i) => i,
+        None => return Ok(None),
+    };
+    if i + 1 >= args.len() {
+        return Err(format!("--config needs a value\n\n{}", USAGE));
+    }
+    let config_path = PathBuf::from(args.remove(i + 1));
+    args.remove(i);
+    return Ok(Some(config_path));
+}
+
+/// Runs a headless command. `args` shouldn't include the program name.
+pub fn run(args: &[String], config_path: &Path) -> Result<(), String> {
+    match parse_command(args)? {
+        Command::Help => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        Command::Export(options) => return export(&options),
+        Command::Follow(options) => return follow(&options),
+        Command::Tui(chat_log_path) => return tui::run(chat_log_path, config_path),
+    }
+}
+
+fn parse_command(args: &[String]) -> Result<Command, String> {
+    let (command, rest) = match args.split_first() {
+        Some(split) => split,
+        None => return Err(USAGE.to_string()),
+    };
+
+    match command.as_str() {
+        "export" => return Ok(Command::Export(parse_export_options(rest)?)),
+        "follow" => return Ok(Command::Follow(parse_follow_options(rest)?)),
+        "tui" => return Ok(Command::Tui(parse_tui_options(rest)?)),
+        "help" | "--help" | "-h" => return Ok(Command::Help),
+        other => return Err(format!("Unknown command '{}'\n\n{}", other, USAGE)),
+    }
+}
+
+fn parse_export_options(args: &[String]) -> Result<ExportOptions, String> {
+    let mut log = None;
+    let mut format = ExportFormat::JsonLines;
+    let mut kind = None;
+    let mut filter = MessageFilter::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        if filter.parse_option(arg, &mut value)? {
+            continue;
+        }
+        match arg.as_str() {
+            "--log" => log = Some(PathBuf::from(value()?)),
+            "--format" => {
+                format = match value()?.as_str() {
+                    "json" | "jsonl" => ExportFormat::JsonLines,
+                    "csv" => ExportFormat::Csv,
+                    other => {
+                        return Err(format!("Unknown format '{}', expected json or csv", other))
+                    }
+                }
+            }
+            "--kind" => {
+                kind = match value()?.as_str() {
+                    "messages" => Some(ExportKind::Messages),
+                    "battles" => Some(ExportKind::Battles),
+                    "greedies" => Some(ExportKind::Greedies),
+                    "all" => None,
+                    other => {
+                        return Err(format!(
+                            "Unknown kind '{}', expected messages, battles, greedies or all",
+                            other
+                        ))
+                    }
+                }
+            }
+            other => return Err(format!("Unknown option '{}'\n\n{}", other, USAGE)),
+        }
+    }
+
+    let log = log.ok_or_else(|| format!("--log is required\n\n{}", USAGE))?;
+    let output = match format {
+        ExportFormat::JsonLines => ExportOutput::JsonLines(kind),
+        ExportFormat::Csv => ExportOutput::Csv(kind.ok_or_else(|| {
+            "CSV output needs a single --kind (messages, battles or greedies)".to_string()
+        })?),
+    };
+
+    return Ok(ExportOptions {
+        log,
+        output,
+        filter,
+    });
+}
+
+fn parse_follow_options(args: &[String]) -> Result<FollowOptions, String> {
+    let mut log = None;
+    let mut format = FollowFormat::Text;
+    let mut interval = Duration::from_millis(2000);
+    let mut from_start = false;
+    let mut filter = MessageFilter::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        if filter.parse_option(arg, &mut value)? {
+            continue;
+        }
+        match arg.as_str() {
+            "--log" => log = Some(PathBuf::from(value()?)),
+            "--format" => {
+                format = match value()?.as_str() {
+                    "text" => FollowFormat::Text,
+                    "json" | "jsonl" => FollowFormat::JsonLines,
+                    other => {
+                        return Err(format!("Unknown format '{}', expected text or json", other))
+                    }
+                }
+            }
+            "--interval" => {
+                let millis = value()?;
+                let millis: u64 = millis.parse().map_err(|_| {
+                    format!("Couldn't read interval '{}', expected milliseconds", millis)
+                })?;
+                if millis < MIN_FOLLOW_INTERVAL_MS {
+                    return Err(format!(
+                        "--interval must be at least {} milliseconds\n\n{}",
+                        MIN_FOLLOW_INTERVAL_MS, USAGE
+                    ));
+                }
+                interval = Duration::from_millis(millis);
+            }
+            "--from-start" => from_start = true,
+            other => return Err(format!("Unknown option '{}'\n\n{}", other, USAGE)),
+        }
+    }
+
+    let log = log.ok_or_else(|| format!("--log is required\n\n{}", USAGE))?;
+    return Ok(FollowOptions {
+        log,
+        format,
+        interval,
+        from_start,
+        filter,
+    });
+}
+
+fn parse_tui_options(args: &[String]) -> Result<Option<PathBuf>, String> {
+    return match args {
+        [] => Ok(None),
+        [option, path] if option == "--log" => Ok(Some(PathBuf::from(path))),
+        _ => Err(format!("Unknown options '{}'\n\n{}", args.join(" "), USAGE)),
+    };
+}
+
+/// Accepts both 2024-01-06 and the chat log's own 2024/01/06.
+fn parse_date(date: &str) -> Result<Date, String> {
+    let date_format = format_description!("[year]-[month]-[day]");
+    return Date::parse(&date.replace('/', "-"), &date_format)
+        .map_err(|_| format!("Couldn't read date '{}', expected YYYY-MM-DD", date));
+}
+
+fn export(options: &ExportOptions) -> Result<(), String> {
+    let file = File::open(&options.log).map_err(|e| {
+        format!(
+            "Couldn't open chat log at {}: {}",
+            options.log.to_string_lossy(),
+            e
+        )
+    })?;
+    let mut parsed = ParsedChatLog::new();
+    parsed.parse_chat_log(BufReader::new(file));
+
+    // The search index gets straight to the messages with the search term in them
+    let candidates = match &options.filter.search {
+        Some(search) if !search.trim().is_empty() => parsed.messages_containing_search_term(search),
+        _ => parsed.messages_in_order_of_creation(),
+    };
+    let messages: Vec<&Message> = candidates
+        .into_iter()
+        .filter(|message| options.filter.matches(message))
+        .collect();
+    // Battles are stored newest first
+    let battles: Vec<&Battle> = parsed
+        .battles
+        .iter()
+        .rev()
+        .filter(|battle| is_on_or_after(battle.date, options.filter.since))
+        .collect();
+
+    let stdout = std::io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let result = match options.output {
+        ExportOutput::JsonLines(kind) => write_json_lines(&mut out, kind, &messages, &battles),
+        ExportOutput::Csv(kind) => write_csv(&mut out, kind, &messages, &battles),
+    };
+    return result
+        .and_then(|_| out.flush())
+        .map_err(|e| format!("Couldn't write export: {}", e));
+}
+
+/// Keeps parsing the chat log with the same incremental parser as the chat tracker window, printing
+/// any new messages that get through the filter.
+fn follow(options: &FollowOptions) -> Result<(), String> {
+    let mut parsed = ParsedChatLog::new();
+    let mut last_printed_id = None;
+    let mut first_parse = true;
+
+    loop {
+        match File::open(&options.log) {
+            Ok(file) => {
+                // Cut short, so read what's there now from the top without waiting
+                if !parsed.parse_chat_log(BufReader::new(file)) {
+                    continue;
+                }
+            }
+            // The log is allowed to go missing whilst following, it may just be getting replaced
+            Err(e) if !first_parse => eprintln!(
+                "Couldn't open chat log at {}: {}",
+                options.log.to_string_lossy(),
+                e
+            ),
+            Err(e) => {
+                return Err(format!(
+                    "Couldn't open chat log at {}: {}",
+                    options.log.to_string_lossy(),
+                    e
+                ))
+            }
+        }
+
+        let new_messages = parsed.messages_after(last_printed_id);
+        if let Some(newest) = new_messages.last() {
+            last_printed_id = Some(newest.id);
+        }
+
+        if !first_parse || options.from_start {
+            let stdout = std::io::stdout();
+            let mut out = stdout.lock();
+            let result = new_messages
+                .iter()
+                .filter(|message| options.filter.matches(message))
+                .try_for_each(|message| match options.format {
+                    FollowFormat::Text => {
+                        writeln!(out, "[{}] {}", message.chat_type.key(), message.contents)
+                    }
+                    FollowFormat::JsonLines => writeln!(out, "{}", message_record(message)),
+                })
+                .and_then(|_| out.flush());
+            match result {
+                Ok(()) => {}
+                // Whatever we were piped into has gone away, nothing left to do
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => return Ok(()),
+                Err(e) => return Err(format!("Couldn't write message: {}", e)),
+            }
+        }
+
+        first_parse = false;
+        std::thread::sleep(options.interval);
+    }
+}
+
+/// Anything without a date is left out when a since date is given, as we can't tell when it
+/// happened.
+fn is_on_or_after(date: Option<Date>, since: Option<Date>) -> bool {
+    return match (date, since) {
+        (_, None) => true,
+        (Some(date), Some(since)) => date >= since,
+        (None, Some(_)) => false,
+    };
+}
+
+struct GreedySummary {
+    hits: u32,
+    battles: u32,
+}
+
+fn greedy_summaries(battles: &[&Battle]) -> BTreeMap<String, GreedySummary> {
+    let mut summaries: BTreeMap<String, GreedySummary> = BTreeMap::new();
+    for battle in battles {
+        for (pirate, hits) in &battle.greedies {
+            let summary = summaries.entry(pirate.clone()).or_insert(GreedySummary {
+                hits: 0,
+                battles: 0,
+            });
+            summary.hits += hits;
+            summary.battles += 1;
+        }
+    }
+    return summaries;
+}
+
+fn message_record(message: &Message) -> serde_json::Value {
+    return json!({
+        "type": "message",
+        "id": message.id,
+        "channel": message.chat_type.key(),
+        "date": message.date.map(|date| date.to_string()),
+        "time": time_string(message.timestamp),
+        "sender": message.sender,
+        "contents": message.contents,
+    });
+}
+
+/// Everything if no kind is given.
+fn write_json_lines<W: Write>(
+    out: &mut W,
+    kind: Option<ExportKind>,
+    messages: &[&Message],
+    battles: &[&Battle],
+) -> std::io::Result<()> {
+    if kind.is_none() || kind == Some(ExportKind::Messages) {
+        for message in messages {
+            writeln!(out, "{}", message_record(message))?;
+        }
+    }
+    if kind.is_none() || kind == Some(ExportKind::Battles) {
+        for battle in battles {
+            let record = json!({
+                "type": "battle",
+                "id": battle.id,
+                "date": battle.date.map(|date| date.to_string()),
+                "attacker": battle.attacker_ship,
+                "defender": battle.defender_ship,
+                "total_greedies": battle.greedies.values().sum::<u32>(),
+                "greedies": battle.greedies,
+            });
+            writeln!(out, "{}", record)?;
+        }
+    }
+    if kind.is_none() || kind == Some(ExportKind::Greedies) {
+        for (pirate, summary) in greedy_summaries(battles) {
+            let record = json!({
+                "type": "greedy_summary",
+                "pirate": pirate,
+                "hits": summary.hits,
+                "battles": summary.battles,
+            });
+            writeln!(out, "{}", record)?;
+        }
+    }
+    return Ok(());
+}
+
+fn write_csv<W: Write>(
+    out: &mut W,
+    kind: ExportKind,
+    messages: &[&Message],
+    battles: &[&Battle],
+) -> std::io::Result<()> {
+    match kind {
+        ExportKind::Messages => {
+            writeln!(out, "id,channel,date,time,sender,contents")?;
+            for message in messages {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{}",
+                    message.id,
+                    message.chat_type.key(),
+                    message
+                        .date
+                        .map(|date| date.to_string())
+                        .unwrap_or_default(),
+                    time_string(message.timestamp),
+                    csv_field(&message.sender),
+                    csv_field(&message.contents)
+                )?;
+            }
+        }
+        ExportKind::Battles => {
+            writeln!(out, "id,date,attacker,defender,total_greedies,greedies")?;
+            for battle in battles {
+                let greedies: Vec<String> = battle
+                    .greedies
+                    .iter()
+                    .map(|(pirate, hits)| format!("{}: {}", pirate, hits))
+                    .collect();
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{}",
+                    battle.id,
+                    battle.date.map(|date| date.to_string()).unwrap_or_default(),
+                    csv_field(&battle.attacker_ship),
+                    csv_field(&battle.defender_ship),
+                    battle.greedies.values().sum::<u32>(),
+                    csv_field(&greedies.join(", "))
+                )?;
+            }
+        }
+        ExportKind::Greedies => {
+            writeln!(out, "pirate,hits,battles")?;
+            for (pirate, summary) in greedy_summaries(battles) {
+                writeln!(
+                    out,
+                    "{},{},{}",
+                    csv_field(&pirate),
+                    summary.hits,
+                    summary.battles
+                )?;
+            }
+        }
+    }
+    return Ok(());
+}
+
+fn time_string(time: Time) -> String {
+    let time_format = format_description!("[hour]:[minute]:[second]");
+    return time.format(&time_format).unwrap();
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        return format!("\"{}\"", field.replace('"', "\"\""));
+    }
+    return field.to_string();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use time::macros::date;
+
+    use crate::chat_log::ParsedChatLog;
+    use crate::ChatType;
+
+    use super::{
+        csv_field, parse_command, take_config_option, write_csv, write_json_lines, Command,
+        ExportKind, ExportOptions, ExportOutput, FollowFormat, FollowOptions, MessageFilter,
+    };
+
+    fn args(args: &str) -> Vec<String> {
+        return args.split(' ').map(|arg| arg.to_string()).collect();
+    }
+
+    #[test]
+    fn test_parse_export_command() {
+        let command = parse_command(&args(
+            "export --log chat.txt --format json --channel trade --since 2024-01-01",
+        ))
+        .unwrap();
+        assert_eq!(
+            command,
+            Command::Export(ExportOptions {
+                log: PathBuf::from("chat.txt"),
+                output: ExportOutput::JsonLines(None),
+                filter: MessageFilter {
+                    channel: ChatType::Trade,
+                    sender: None,
+                    search: None,
+                    since: Some(date!(2024 - 01 - 01)),
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_follow_command() {
+        let command = parse_command(&args(
+            "follow --log chat.txt --format json --sender Bob --search iron --interval 500",
+        ))
+        .unwrap();
+        assert_eq!(
+            command,
+            Command::Follow(FollowOptions {
+                log: PathBuf::from("chat.txt"),
+                format: FollowFormat::JsonLines,
+                interval: Duration::from_millis(500),
+                from_start: false,
+                filter: MessageFilter {
+                    channel: ChatType::All,
+                    sender: Some("Bob".to_string()),
+                    search: Some("iron".to_string()),
+                    since: None,
+                },
+            })
+        );
+        assert!(parse_command(&args("follow --log chat.txt --format csv")).is_err());
+        assert!(parse_command(&args("follow --log chat.txt --interval soon")).is_err());
+        assert!(parse_command(&args("follow --log chat.txt --interval 0")).is_err());
+        assert!(parse_command(&args("follow --log chat.txt --interval 100")).is_ok());
+    }
+
+    #[test]
+    fn test_message_filter() {
+        let log = "[16:05:04] Someone trade chats, \"Buying IRON\"
+[16:05:05] Bob trade chats, \"Selling iron\"
+[16:05:06] Bob tells ye, \"Got any iron?\"";
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        let messages = parsed.messages_in_order_of_creation();
+
+        let filter = MessageFilter {
+            channel: ChatType::Trade,
+            search: Some("iron".to_string()),
+            ..MessageFilter::default()
+        };
+        let matching: Vec<_> = messages.iter().filter(|m| filter.matches(m)).collect();
+        assert_eq!(matching.len(), 2);
+
+        let filter = MessageFilter {
+            sender: Some("bob".to_string()),
+            ..MessageFilter::default()
+        };
+        let matching: Vec<_> = messages.iter().filter(|m| filter.matches(m)).collect();
+        assert_eq!(matching.len(), 2);
+        assert_eq!(matching[1].chat_type, ChatType::Tell);
+    }
+
+    #[test]
+    fn test_parse_export_command_errors() {
+        assert!(parse_command(&args("export")).is_err());
+        assert!(parse_command(&args("export --log")).is_err());
+        assert!(parse_command(&args("export --log chat.txt --channel parley")).is_err());
+        assert!(parse_command(&args("export --log chat.txt --since yesterday")).is_err());
+        assert!(parse_command(&args("export --log chat.txt --format csv")).is_err());
+        assert!(parse_command(&args("frobnicate")).is_err());
+    }
+
+    #[test]
+    fn test_csv_field_escaping() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_export_output() {
+        let log = "===== 2024/01/06 =====
+[16:05:04] Someone trade chats, \"Buying iron, 10 each\"
+[02:01:19] Mean Shad has grappled Shifty Shiner. A melee breaks out between the crews!
+[02:01:54] Bob delivers an overwhelming barrage against Petty Robert, causing some treasure to fall from their grip
+[02:03:00] Game Over";
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        let messages = parsed.messages_in_order_of_creation();
+        let battles: Vec<_> = parsed.battles.iter().collect();
+
+        let mut out = Vec::new();
+        write_csv(&mut out, ExportKind::Messages, &messages, &battles).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        assert_eq!(
+            csv,
+            "id,channel,date,time,sender,contents\n2,trade,2024-01-06,16:05:04,Someone,\"[16:05:04] Someone trade chats, \"\"Buying iron, 10 each\"\"\"\n"
+        );
+
+        let mut out = Vec::new();
+        write_json_lines(&mut out, None, &messages, &battles).unwrap();
+        let json = String::from_utf8(out).unwrap();
+        let lines: Vec<serde_json::Value> = json
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["type"], "message");
+        assert_eq!(lines[0]["channel"], "trade");
+        assert_eq!(lines[1]["type"], "battle");
+        assert_eq!(lines[1]["greedies"]["Bob"], 1);
+        assert_eq!(lines[2]["type"], "greedy_summary");
+        assert_eq!(lines[2]["pirate"], "Bob");
+    }
+
+    #[test]
+    fn test_take_config_option() {
+        let mut tui_args = args("tui --config my.toml --log chat.txt");
+        assert_eq!(
+            take_config_option(&mut tui_args),
+            Ok(Some(PathBuf::from("my.toml")))
+        );
+        assert_eq!(tui_args, args("tui --log chat.txt"));
+
+        let mut no_config = args("tui");
+        assert_eq!(take_config_option(&mut no_config), Ok(None));
+        assert!(take_config_option(&mut args("--config")).is_err());
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use notify::RecommendedWatcher;
+
+use crate::file_watch::watch_file;
+use crate::{Config, CONFIG_VERSION};
+
+/// Each one takes a config of its index's version to the next version. Settings added with a
+/// default don't need one.
+const MIGRATIONS: [fn(&mut toml::Table); CONFIG_VERSION as usize] = [
+    // Nothing's changed shape since before versions, it just gets one
+    |_| {},
+];
+
+/// Where the config used to be kept, in the working directory.
+const OLD_CONFIG_PATH: &str = "puzzle-pirates-chat-tracker.toml";
+const CONFIG_DIR: &str = "puzzle-pirates-chat-tracker";
+const CONFIG_FILE: &str = "config.toml";
+
+/// Where the config is kept unless `--config` says otherwise, in the platform's config folder.
+pub fn default_config_path() -> PathBuf {
+    return match dirs::config_dir() {
+        Some(config_dir) => config_dir.join(CONFIG_DIR).join(CONFIG_FILE),
+        None => PathBuf::from(OLD_CONFIG_PATH),
+    };
+}
+
+/// The config at `config_path`, or the defaults if there isn't one yet. A config from before it
+/// moved to the config folder is picked up from the working directory, and gets saved to the new
+/// place.
+pub fn load_config(config_path: &Path) -> Result<Config, String> {
+    let read_error = |e: String| {
+        return format!(
+            "Couldn't read config file at {}: {}",
+            config_path.to_string_lossy(),
+            e
+        );
+    };
+    let contents = match fs::read_to_string(config_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            // Only looked for if --config wasn't given
+            if config_path != default_config_path() {
+                return Ok(Config::default());
+            }
+            let contents = match fs::read_to_string(OLD_CONFIG_PATH) {
+                Ok(contents) => contents,
+                Err(_) => return Ok(Config::default()),
+            };
+            let config = parse_config(&contents).map_err(read_error)?;
+            // Still usable from the old place if it can't be moved
+            if let Err(e) = write_config(&config, config_path) {
+                eprintln!("{}", e);
+            }
+            return Ok(config);
+        }
+        Err(e) => return Err(read_error(e.to_string())),
+    };
+    return parse_config(&contents).map_err(read_error);
+}
+
+/// Brings older configs up to date before reading them. Saved searches are told apart by name, so
+/// each name can only be used once.
+pub fn parse_config(contents: &str) -> Result<Config, String> {
+    let config = parse_config_version(contents)?;
+    let mut names = BTreeSet::new();
+    for saved in &config.saved_searches {
+        if !names.insert(&saved.name) {
+            return Err(format!(
+                "There's more than one saved search called \"{}\"",
+                saved.name
+            ));
+        }
+    }
+    return Ok(config);
+}
+
+fn parse_config_version(contents: &str) -> Result<Config, String> {
+    let mut table: toml::Table = toml::from_str(contents).map_err(|e| e.to_string())?;
+    let version = match table.get("version") {
+        None => 0,
+        Some(toml::Value::Integer(version)) if *version >= 0 => *version as u32,
+        Some(version) => return Err(format!("{} isn't a config version", version)),
+    };
+    if version > CONFIG_VERSION {
+        return Err(format!(
+            "It's from a newer version of the tracker (config version {}), this one reads up to version {}",
+            version, CONFIG_VERSION
+        ));
+    }
+    if version == CONFIG_VERSION {
+        // Straight from the text, so errors say which line they're on
+        return toml::from_str(contents).map_err(|e| e.to_string());
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut table);
+    }
+    table.insert(
+        "version".to_string(),
+        toml::Value::Integer(CONFIG_VERSION as i64),
+    );
+    return toml::Value::Table(table)
+        .try_into()
+        .map_err(|e: toml::de::Error| e.to_string());
+}
+
+/// Writes to a temporary file first, so a crash mid write can't leave half a config.
+pub fn write_config(config: &Config, config_path: &Path) -> Result<(), String> {
+    let write_error = |e: String| {
+        return format!(
+            "Couldn't write config file at {}: {}",
+            config_path.to_string_lossy(),
+            e
+        );
+    };
+    let toml = toml::to_string(config).map_err(|e| write_error(e.to_string()))?;
+    if let Some(config_dir) = config_path.parent() {
+        fs::create_dir_all(config_dir).map_err(|e| write_error(e.to_string()))?;
+    }
+    let temp_path = config_path.with_extension("toml.tmp");
+    fs::write(&temp_path, toml).map_err(|e| write_error(e.to_string()))?;
+    fs::rename(&temp_path, config_path).map_err(|e| write_error(e.to_string()))?;
+    return Ok(());
+}
+
+/// Keeps the config file and the window's config in step. Changes made to the file by hand are
+/// merged with changes made in the window, rather than one overwriting the other.
+pub struct ConfigFile {
+    path: PathBuf,
+    /// The config as last read from or written to the file, what changes on either side are from
+    on_disk: Config,
+    /// Why the file couldn't be read. Nothing's written until it's fixed, so it isn't lost.
+    pub error: Option<String>,
+    /// Set by the watcher when the file's been changed
+    changed: Arc<AtomicBool>,
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl ConfigFile {
+    /// The file at `path` and the config in it. If it can't be read, the config starts from the
+    /// defaults and the error's kept to show.
+    pub fn load(path: PathBuf) -> (ConfigFile, Config) {
+        let (config, error) = match load_config(&path) {
+            Ok(config) => (config, None),
+            Err(e) => {
+                eprintln!("{}", e);
+                (Config::default(), Some(e))
+            }
+        };
+        let config_file = ConfigFile {
+            path,
+            on_disk: config.clone(),
+            error,
+            changed: Arc::new(AtomicBool::new(false)),
+            _watcher: None,
+        };
+        return (config_file, config);
+    }
+
+    /// Calls `on_change` whenever the file's changed, so it can be synced. Without a watcher, hand
+    /// edits are still picked up the next time the window saves.
+    pub fn watch(&mut self, on_change: impl Fn() + Send + 'static) {
+        // The folder has to be there to be watched, the file doesn't
+        if let Some(config_dir) = self.path.parent() {
+            let _ = fs::create_dir_all(config_dir);
+        }
+        let changed = self.changed.clone();
+        let watcher = watch_file(&self.path, move || {
+            changed.store(true, Ordering::SeqCst);
+            on_change();
+        });
+        match watcher {
+            Ok(watcher) => self._watcher = Some(watcher),
+            Err(e) => eprintln!(
+                "Couldn't watch config file at {} for changes: {}",
+                self.path.to_string_lossy(),
+                e
+            ),
+        }
+    }
+
+    /// Whether the file's been changed since this was last asked.
+    pub fn has_changed(&self) -> bool {
+        return self.changed.swap(false, Ordering::SeqCst);
+    }
+
+    /// Merges `config`, from the window, with what's in the file now, and saves the result if the
+    /// file doesn't have it already. Where both changed the same setting, the file's kept.
+    pub fn sync(&mut self, config: &Config) -> Config {
+        let on_disk = match load_config(&self.path) {
+            Ok(on_disk) => on_disk,
+            Err(e) => {
+                if self.error.as_ref() != Some(&e) {
+                    eprintln!("{}", e);
+                }
+                self.error = Some(e);
+                return config.clone();
+            }
+        };
+        self.error = None;
+        let merged = if on_disk == self.on_disk {
+            config.clone()
+        } else {
+            merge_configs(&self.on_disk, config, &on_disk)
+        };
+        self.on_disk = on_disk;
+        if merged != self.on_disk {
+            match write_config(&merged, &self.path) {
+                Ok(()) => self.on_disk = merged.clone(),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        return merged;
+    }
+}
+
+/// `theirs` with the settings changed from `base` in `ours` that `theirs` left alone. Tables
+/// are merged setting by setting, anything else is changed as a whole.
+pub fn merge_configs(base: &Config, ours: &Config, theirs: &Config) -> Config {
+    let tables = (
+        toml::Value::try_from(base),
+        toml::Value::try_from(ours),
+        toml::Value::try_from(theirs),
+    );
+    let (base, ours, mut merged) = match tables {
+        (
+            Ok(toml::Value::Table(base)),
+            Ok(toml::Value::Table(ours)),
+            Ok(toml::Value::Table(theirs)),
+        ) => (base, ours, theirs),
+        _ => return theirs.clone(),
+    };
+    merge_tables(&base, &ours, &mut merged);
+    return toml::Value::Table(merged)
+        .try_into()
+        .unwrap_or_else(|_| theirs.clone());
+}
+
+fn merge_tables(base: &toml::Table, ours: &toml::Table, theirs: &mut toml::Table) {
+    let keys: BTreeSet<&String> = base.keys().chain(ours.keys()).collect();
+    for key in keys {
+        let (base, ours) = (base.get(key), ours.get(key));
+        if ours == base {
+            continue;
+        }
+        if let (
+            Some(toml::Value::Table(base)),
+            Some(toml::Value::Table(ours)),
+            Some(toml::Value::Table(theirs)),
+        ) = (base, ours, theirs.get_mut(key))
+        {
+            merge_tables(base, ours, theirs);
+            continue;
+        }
+        // Changed on both sides, theirs is kept
+        if theirs.get(key) != base {
+            continue;
+        }
+        match ours {
+            Some(ours) => theirs.insert(key.clone(), ours.clone()),
+            None => theirs.remove(key),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_configs, parse_config, write_config, ConfigFile};
+    use crate::appearance::Theme;
+    use crate::{Config, CONFIG_VERSION};
+
+    #[test]
+    fn test_parse_config() {
+        // From before versions
+        let config = parse_config("message_limit = 50").unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.message_limit.0, 50);
+
+        let error = parse_config("message_limit = \"lots\"").unwrap_err();
+        assert!(error.contains("message_limit"));
+        let error =
+            parse_config("version = 1\nmessage_limit = 50\nmessage_limit = 60").unwrap_err();
+        assert!(error.contains("line 3"));
+        let error = parse_config("version = 99").unwrap_err();
+        assert!(error.contains("newer version of the tracker"));
+        let error = parse_config(
+            "[[saved_searches]]\nname = \"Iron\"\nquery = \"iron\"\n\n[[saved_searches]]\nname = \"Iron\"\nquery = \"ore\"",
+        )
+        .unwrap_err();
+        assert!(error.contains("more than one saved search called \"Iron\""));
+    }
+
+    #[test]
+    fn test_write_config() {
+        let dir =
+            std::env::temp_dir().join(format!("chat-tracker-config-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config_path = dir.join("nested").join("config.toml");
+
+        let mut config = Config::default();
+        config.message_limit.0 = 50;
+        write_config(&config, &config_path).unwrap();
+        let written = std::fs::read_to_string(&config_path).unwrap();
+        assert!(written.starts_with(&format!("version = {}", CONFIG_VERSION)));
+        assert_eq!(parse_config(&written).unwrap(), config);
+        assert!(!config_path.with_extension("toml.tmp").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_merge_configs() {
+        let base = Config::default();
+        let mut ours = base.clone();
+        ours.message_limit.0 = 50;
+        ours.appearance.font_scale = 1.5;
+        ours.pirate_names = vec!["Ourpirate".to_string()];
+        let mut theirs = base.clone();
+        theirs.appearance.theme = Theme::Light;
+        theirs.pirate_names = vec!["Handedited".to_string()];
+        theirs.chat_log_path = Some("chat.log".into());
+
+        let merged = merge_configs(&base, &ours, &theirs);
+        assert_eq!(merged.message_limit.0, 50);
+        assert_eq!(merged.appearance.font_scale, 1.5);
+        assert_eq!(merged.appearance.theme, Theme::Light);
+        assert_eq!(merged.chat_log_path, Some("chat.log".into()));
+        // Changed on both sides
+        assert_eq!(merged.pirate_names, vec!["Handedited".to_string()]);
+
+        // Settings that are left out when unset
+        let merged = merge_configs(&theirs, &base, &theirs);
+        assert_eq!(merged.chat_log_path, None);
+        assert_eq!(merged.appearance.theme, Theme::Dark);
+    }
+
+    #[test]
+    fn test_config_file_sync() {
+        let dir = std::env::temp_dir().join(format!(
+            "chat-tracker-config-sync-test-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config_path = dir.join("config.toml");
+
+        let (mut config_file, mut config) = ConfigFile::load(config_path.clone());
+        assert_eq!(config, Config::default());
+        // Nothing's changed, so nothing's written
+        assert_eq!(config_file.sync(&config), config);
+        assert!(!config_path.exists());
+
+        config.message_limit.0 = 50;
+        let config = config_file.sync(&config);
+        assert_eq!(
+            parse_config(&std::fs::read_to_string(&config_path).unwrap()).unwrap(),
+            config
+        );
+
+        // Edited by hand, then in the window before it's synced
+        let hand_edited = std::fs::read_to_string(&config_path)
+            .unwrap()
+            .replace("hide_npc_messages = []", "hide_npc_messages = [\"global\"]");
+        std::fs::write(&config_path, hand_edited).unwrap();
+        let mut edited = config.clone();
+        edited.newest_at_bottom = true;
+        let config = config_file.sync(&edited);
+        assert_eq!(config.message_limit.0, 50);
+        assert!(config.newest_at_bottom);
+        assert_eq!(config.hide_npc_messages.len(), 1);
+        assert_eq!(
+            parse_config(&std::fs::read_to_string(&config_path).unwrap()).unwrap(),
+            config
+        );
+
+        // Broken by hand
+        std::fs::write(&config_path, "message_limit = \"lots\"").unwrap();
+        let mut edited = config.clone();
+        edited.message_limit.0 = 60;
+        assert_eq!(config_file.sync(&edited), edited);
+        assert!(config_file.error.is_some());
+        assert_eq!(
+            std::fs::read_to_string(&config_path).unwrap(),
+            "message_limit = \"lots\""
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,40 @@
+use std::path::Path;
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Calls `on_change` whenever the file at `path` is written to, from the watcher's own thread.
+/// Watches the folder it's in, since files can be replaced instead of written to. Keep the
+/// watcher around for as long as it's needed, dropping it stops it.
+pub fn watch_file(
+    path: &Path,
+    on_change: impl Fn() + Send + 'static,
+) -> notify::Result<RecommendedWatcher> {
+    let file_name = path.file_name().map(|name| name.to_os_string());
+    let watched_path = path.to_string_lossy().to_string();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let changed = match event {
+            // Reading the file shows up as access, that's just us
+            Ok(event) => {
+                !matches!(event.kind, EventKind::Access(_))
+                    && event
+                        .paths
+                        .iter()
+                        .any(|path| path.file_name() == file_name.as_deref())
+            }
+            // Might have missed a change, so say there was one anyway
+            Err(e) => {
+                eprintln!("Error watching {}: {}", watched_path, e);
+                true
+            }
+        };
+        if changed {
+            on_change();
+        }
+    })?;
+    let folder = match path.parent() {
+        Some(folder) if !folder.as_os_str().is_empty() => folder,
+        _ => Path::new("."),
+    };
+    watcher.watch(folder, RecursiveMode::NonRecursive)?;
+    return Ok(watcher);
+}
@@ -1,28 +1,110 @@
 #![windows_subsystem = "windows"]
 use std::collections::BTreeMap;
-use std::fs;
-use std::fs::File;
-use std::io::{BufReader, Write};
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
 
+#[cfg(not(target_arch = "wasm32"))]
 use eframe::egui::ViewportBuilder;
-use egui::{Context, Ui};
 use serde::{Deserialize, Serialize};
 use time::{Date, Time};
 
-use chat_log::ParsedChatLog;
-
-const PIRATE_INFO_URL: &str = "https://emerald.puzzlepirates.com/yoweb/pirate.wm?target=";
-
+#[cfg(not(target_arch = "wasm32"))]
+use alerts::{AlertEngine, SystemAlertSink};
+use alerts::{AlertRule, WindowAlerts};
+use app::TrackerApp;
+use appearance::Appearance;
+#[cfg(not(target_arch = "wasm32"))]
+use config_file::{default_config_path, ConfigFile};
+use npcs::Npcs;
+#[cfg(not(target_arch = "wasm32"))]
+use parser_worker::{ParserCommand, ParserWorker};
+use search_query::SearchOptions;
+
+#[cfg(not(target_arch = "wasm32"))]
+const DEFAULT_ARCHIVE_DIR: &str = "puzzle-pirates-chat-tracker-archive";
+const WINDOW_TITLE: &str = "Puzzle Pirates Chat Tracker";
+
+mod alerts;
+mod app;
+mod appearance;
+#[cfg(not(target_arch = "wasm32"))]
+mod archive;
 mod chat_log;
-
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg(not(target_arch = "wasm32"))]
+mod cli;
+#[cfg(not(target_arch = "wasm32"))]
+mod config_file;
+#[cfg(not(target_arch = "wasm32"))]
+mod file_watch;
+mod message_list;
+mod mutes;
+mod npcs;
+#[cfg(not(target_arch = "wasm32"))]
+mod parser_worker;
+mod pirate_profile;
+mod search_index;
+mod search_query;
+#[cfg(not(target_arch = "wasm32"))]
+mod tui;
+
+/// Bumped whenever a change to Config needs older config files changing to fit. Configs without
+/// a version are from before versions, version 0.
+const CONFIG_VERSION: u32 = 1;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 struct Config {
+    /// Which shape the config file is in, see CONFIG_VERSION
+    #[serde(default)]
+    version: u32,
     chat_log_path: Option<PathBuf>,
     #[serde(default)]
     message_limit: MessageLimit,
+    /// Where parsed chat is kept between runs. Defaults to DEFAULT_ARCHIVE_DIR.
+    #[serde(default)]
+    archive_dir: Option<PathBuf>,
+    /// The user's pirates, for the Mentions tab
+    #[serde(default)]
+    pirate_names: Vec<String>,
+    /// Pirates whose messages are hidden
+    #[serde(default)]
+    ignored_pirates: Vec<String>,
+    /// Regular expressions. Matching messages are hidden.
+    #[serde(default)]
+    mute_rules: Vec<String>,
+    /// On top of the NPCs that come with the tracker
+    #[serde(default)]
+    known_npcs: Vec<String>,
+    /// Chat tabs that leave out messages from NPCs
+    #[serde(default)]
+    hide_npc_messages: Vec<ChatType>,
+    /// How often the chat log is checked for new lines, if changes to it can't be watched for
+    #[serde(default)]
+    poll_interval_ms: PollInterval,
+    /// Chat tabs show the newest messages at the bottom and follow them as they come in
+    #[serde(default)]
+    newest_at_bottom: bool,
+    #[serde(default)]
+    appearance: Appearance,
+    /// Each one gets its own tab
+    #[serde(default)]
+    saved_searches: Vec<SavedSearch>,
+    #[serde(default)]
+    alert_rules: Vec<AlertRule>,
+    /// What the user's written about pirates, by name
+    #[serde(default)]
+    pirate_notes: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+struct SavedSearch {
+    name: String,
+    query: String,
+    #[serde(default)]
+    channel: ChatType,
+    #[serde(default)]
+    options: SearchOptions,
 }
 
 #[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
@@ -34,24 +116,66 @@ impl Default for MessageLimit {
     }
 }
 
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+struct PollInterval(u64);
+
+impl Default for PollInterval {
+    fn default() -> Self {
+        PollInterval(2000)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl PollInterval {
+    fn duration(&self) -> Duration {
+        return Duration::from_millis(self.0);
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             chat_log_path: None,
             message_limit: MessageLimit::default(),
+            archive_dir: None,
+            pirate_names: vec![],
+            ignored_pirates: vec![],
+            mute_rules: vec![],
+            known_npcs: vec![],
+            hide_npc_messages: vec![],
+            poll_interval_ms: PollInterval::default(),
+            newest_at_bottom: false,
+            appearance: Appearance::default(),
+            saved_searches: vec![],
+            alert_rules: vec![],
+            pirate_notes: BTreeMap::new(),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Battle {
-    _id: u32,
+    id: u32,
     attacker_ship: String,
     defender_ship: String,
     greedies: BTreeMap<String, u32>,
+    date: Option<Date>,
+    started_at: Option<Time>,
+}
+
+impl Battle {
+    /// Stays the same across restarts and rereads of the chat log, unlike the id.
+    fn identity(&self) -> u64 {
+        let started = format!(
+            "{:?} {} {}",
+            self.started_at, self.attacker_ship, self.defender_ship
+        );
+        return chat_log::line_identity(self.date, &started);
+    }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 struct Message {
     id: u32,
     timestamp: Time,
@@ -59,16 +183,24 @@ struct Message {
     sender: String,
     // Need to decide what a message that has no date means for sorting on search results.
     date: Option<Date>,
+    chat_type: ChatType,
 }
 
 impl Message {
-    fn new(contents: String, sender: String, timestamp: Time, id: u32) -> Self {
+    fn new(
+        contents: String,
+        sender: String,
+        timestamp: Time,
+        id: u32,
+        chat_type: ChatType,
+    ) -> Self {
         return Message {
             id,
             contents,
             sender,
             timestamp,
             date: None,
+            chat_type,
         };
     }
 
@@ -79,396 +211,225 @@ impl Message {
         return (sender_start, sender_end);
     }
 
+    // The window lays out whole messages, only the terminal needs them in pieces
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
     fn timestamp_from_message(&self) -> &str {
         return &self.contents[0..=self.contents.find(']').unwrap()];
     }
 
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
     fn contents_without_sender(&self) -> String {
         return self.contents[self.sender_indexes().1..self.contents.len()].to_string();
     }
 
-    /// Players can't have whitespace in their names, but NPCs can.
-    /// Not sure if there are NPCs with no whitespace in their names.
-    fn is_sender_npc(&self) -> bool {
-        return self.sender.split_whitespace().count() > 1;
+    /// Stays the same across restarts and rereads of the chat log, unlike the id.
+    fn identity(&self) -> u64 {
+        return chat_log::line_identity(self.date, &self.contents);
+    }
+
+    fn is_sender_npc(&self, npcs: &Npcs) -> bool {
+        return npcs.is_npc(&self.sender);
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 fn main() {
     // TODO: Track personal plunder from battles
     // TODO: Message monitor - look for messages in trade chat like 'message contains BUYING <some text> <item>, but only if the item is before a SELLING word in the same message etc)
     // TODO: Warning if chat log is over a certain size?
-    // TODO: Filters for the chat tab? Search by word, pirate name etc - Expand to allow for multiple word searches (allow regex?)
-    // TODO: Configurable delay
-    // TODO: Error on failed parse (wrong file given for example)
-    // TODO: Unread indicator on chat tabs
-    // TODO: Alert/Sound/Notification on chat containing search term
     // TODO: Force a reparse when search term updates (with debounce period?)
     // TODO: Look into the invalid utf-8 errors we get from the chat log, might be useful encoded data?
-    // TODO: Have the different chat types differ in some way in all chat
     // TODO: Show the date timestamp beside messages (toggleable) - It's handy when looking back at older messages
     // TODO: User settings tab
-    // TODO: User settings, let user pick colour for each chat
-    // TODO: User settings, increase font size
-    // TODO: User settings, Toggle NPC chat messages
-
-    let parsed_stuff = Arc::new(Mutex::new(ParsedChatLog::new()));
 
-    let config_path = Path::new("puzzle-pirates-chat-tracker.toml");
-
-    let config: Arc<Mutex<Config>> = Arc::new(Mutex::new(Config::default()));
-    if let Ok(contents) = fs::read_to_string(config_path) {
-        let parsed_config: Config = toml::from_str(&contents).unwrap();
-        *config.lock().unwrap() = parsed_config;
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        attach_to_parent_console();
     }
-
-    let mut selected_panel = Tabs::Chat(ChatType::All);
-    let mut last_reparse = Instant::now();
-    let timer_threshold = Duration::from_millis(2000);
-
-    let search_term = Arc::new(Mutex::new(String::new()));
-
-    if let Some(chat_log_path) = &config.lock().unwrap().chat_log_path {
-        let reader = open_chat_log(chat_log_path);
-        parsed_stuff.lock().unwrap().parse_chat_log(reader);
+    let config_path = match cli::take_config_option(&mut args) {
+        Ok(config_path) => config_path.unwrap_or_else(default_config_path),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if !args.is_empty() {
+        if let Err(e) = cli::run(&args, &config_path) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
     }
 
-    let eframe_ctx = Arc::new(Mutex::new(None::<Context>));
-
-    {
-        let config = config.clone();
-        let parsed_stuff = parsed_stuff.clone();
-        let eframe_ctx = eframe_ctx.clone();
-
-        std::thread::spawn(move || loop {
-            let now = Instant::now();
-            let time_since_last_reparse = now - last_reparse;
-            if time_since_last_reparse > timer_threshold {
-                dbg!("Reparsing");
-                if let Some(chat_log_path) = &config.lock().unwrap().chat_log_path {
-                    let reader = open_chat_log(chat_log_path);
-                    parsed_stuff.lock().unwrap().parse_chat_log(reader);
-                }
-                if let Some(ctx) = eframe_ctx.lock().unwrap().as_ref() {
-                    ctx.request_repaint();
-                }
-                last_reparse = Instant::now();
-            }
-            std::thread::sleep(Duration::from_millis(500));
-        });
-    }
+    // A broken config shouldn't stop the tracker, the window says what's wrong with it
+    let (mut config_file, config) = ConfigFile::load(config_path);
+    let config: Arc<Mutex<Config>> = Arc::new(Mutex::new(config));
 
-    let config = config.clone();
-    let parsed_stuff = parsed_stuff.clone();
     let options = eframe::NativeOptions {
         viewport: ViewportBuilder::default(),
+        // The theme comes from the config instead
+        follow_system_theme: false,
         ..Default::default()
     };
-    let mut ctx_been_cloned = false;
-    eframe::run_simple_native(
-        "Puzzle Pirates Chat Tracker",
+    eframe::run_native(
+        WINDOW_TITLE,
         options,
-        move |ctx, _frame| {
-            if !ctx_been_cloned {
-                *eframe_ctx.lock().unwrap() = Some(ctx.clone());
-                ctx_been_cloned = true;
-            }
-
-            let original_message_limit = config.lock().unwrap().message_limit;
-
-            egui::CentralPanel::default().show(ctx, |ui| {
-                if config.lock().unwrap().chat_log_path.is_none() {
-                    ui.label("No chat log given. Please hit 'Open chat log'");
-                }
-                if ui.button("Open chat log").clicked() {
-                    if let Some(path) = rfd::FileDialog::new().pick_file() {
-                        let config = {
-                            let mut config = config.lock().unwrap();
-                            config.chat_log_path = Some(path);
-                            config.clone()
-                        };
-
-                        if let Err(_) = write_config_to_config_file(&config, config_path) {
-                            eprintln!(
-                                "Couldn't open config file at {}",
-                                config_path.to_string_lossy()
-                            );
-                        }
-
-                        let mut parsed = parsed_stuff.lock().unwrap();
-                        *parsed = ParsedChatLog::new();
-                        if let Some(chat_log_path) = &config.chat_log_path {
-                            let reader = open_chat_log(chat_log_path);
-                            parsed.parse_chat_log(reader);
-                        }
-                    }
-
-                    // TODO: Drag and drop file
-                }
-                if ui.button("Reload chat log").clicked() {
-                    if let Some(chat_log_path) = &config.lock().unwrap().chat_log_path {
-                        let reader = open_chat_log(chat_log_path);
-                        // Wipe our progress on reload
-                        let mut parsed = parsed_stuff.lock().unwrap();
-                        *parsed = ParsedChatLog::new();
-                        //  TODO: Might want to send a message to the background thread instead of doing this parse here
-                        parsed.parse_chat_log(reader);
+        Box::new(move |cc| {
+            let ctx = cc.egui_ctx.clone();
+            let config_ctx = ctx.clone();
+            config_file.watch(move || config_ctx.request_repaint());
+            let appearance = config.lock().unwrap().appearance.clone();
+            appearance.apply(&ctx);
+            appearance.load_font(&ctx);
+            let window_alerts = WindowAlerts::default();
+            let mut parser_window_alerts = window_alerts.clone();
+            let mut alert_engine = AlertEngine::new();
+            let alert_config = config.clone();
+            let mut parser = ParserWorker::spawn(
+                config.lock().unwrap().poll_interval_ms.duration(),
+                move |parsed, opened| {
+                    if opened {
+                        alert_engine.reset();
+                        parser_window_alerts.clear();
                     }
-                }
-
-                ui.horizontal_wrapped(|ui| {
-                    ui.selectable_value(&mut selected_panel, Tabs::Chat(ChatType::All), "All chat");
-                    ui.selectable_value(&mut selected_panel, Tabs::Chat(ChatType::Chat), "Chat");
-                    ui.selectable_value(
-                        &mut selected_panel,
-                        Tabs::Chat(ChatType::Trade),
-                        "Trade chat",
+                    let alert_rules = alert_config.lock().unwrap().alert_rules.clone();
+                    alert_engine.check(
+                        parsed,
+                        &alert_rules,
+                        &mut [&mut SystemAlertSink, &mut parser_window_alerts],
                     );
-                    ui.selectable_value(
-                        &mut selected_panel,
-                        Tabs::Chat(ChatType::Global),
-                        "Global chat",
-                    );
-                    ui.selectable_value(&mut selected_panel, Tabs::Chat(ChatType::Tell), "Tells");
-                    ui.selectable_value(&mut selected_panel, Tabs::SearchChat, "Search chat");
-                    ui.selectable_value(&mut selected_panel, Tabs::GreedyHits, "Greedies");
-                    ui.selectable_value(&mut selected_panel, Tabs::Settings, "Settings");
-                });
-
-                let message_limit = config.lock().unwrap().message_limit.0 as usize;
-                match selected_panel {
-                    Tabs::GreedyHits => greedy_ui(ui, &parsed_stuff.lock().unwrap()),
-                    Tabs::Chat(chat_type) => {
-                        chat_ui(ui, &parsed_stuff.lock().unwrap(), chat_type, message_limit)
-                    }
-                    Tabs::SearchChat => search_chat_ui(
-                        ui,
-                        &parsed_stuff.lock().unwrap(),
-                        &mut search_term.lock().unwrap(),
-                        message_limit,
-                    ),
-                    Tabs::Settings => settings_ui(ui, &mut config.lock().unwrap().message_limit.0),
-                }
-            });
-
-            {
-                let config = config.lock().unwrap();
-                if config.message_limit != original_message_limit {
-                    if let Err(_) = write_config_to_config_file(&config, config_path) {
-                        eprintln!(
-                            "Couldn't open config file at {}",
-                            config_path.to_string_lossy()
-                        );
-                    }
-                }
+                },
+                move || ctx.request_repaint(),
+            );
+            if let Some(open) = ParserCommand::open(&config.lock().unwrap()) {
+                parser.send(open);
             }
-        },
+            return Box::new(TrackerApp::new(config, parser, config_file, window_alerts));
+        }),
     )
         .unwrap();
 }
 
-fn settings_ui(ui: &mut Ui, message_limit: &mut u64) {
-    ui.label("Message limit");
-    let mut tmp = message_limit.to_string();
-    ui.text_edit_singleline(&mut tmp);
-
-    if let Ok(new_message_limit) = tmp.parse::<u64>() {
-        *message_limit = new_message_limit;
+/// The window doesn't get a console on Windows, so commands borrow the one they were run from to
+/// print to.
+#[cfg(windows)]
+fn attach_to_parent_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    // Fails when run from outside a console, where there's nowhere to print to anyway
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
     }
 }
 
-fn search_chat_ui(
-    ui: &mut Ui,
-    parsed_stuff: &ParsedChatLog,
-    search_term: &mut String,
-    message_limit: usize,
-) {
-    egui::ScrollArea::vertical().show(ui, |ui| {
-        ui.heading("Filtered chat");
-        let search_label = ui.label("Search term");
-        ui.text_edit_singleline(search_term)
-            .labelled_by(search_label.id);
-
-        let matching_messages = parsed_stuff.messages_containing_search_term(search_term);
-        if matching_messages.is_empty() {
-            ui.label("No chat messages found.");
-        }
-        for (i, message) in matching_messages.iter().rev().enumerate() {
-            if i >= message_limit {
-                break;
-            }
-
-            ui.separator();
-            if message.is_sender_npc() {
-                // Probably an NPC, won't have a pirate page to go to
-                append_npc_chat_line(message, ui);
-            } else {
-                append_player_chat_line(message, ui);
-            }
-        }
-    });
-}
-
-fn chat_ui(ui: &mut Ui, parsed_stuff: &ParsedChatLog, chat_type: ChatType, message_limit: usize) {
-    egui::ScrollArea::vertical().show(ui, |ui| {
-        let heading = match chat_type {
-            ChatType::Chat => "Chat",
-            ChatType::Trade => "Trade chat",
-            ChatType::Global => "Global chat",
-            ChatType::Tell => "Tells",
-            ChatType::All => "All chat",
-        };
-        ui.heading(heading);
-
-        if chat_type == ChatType::All {
-            let messages = parsed_stuff.messages_in_order_of_creation();
-            if messages.is_empty() {
-                ui.label("No chat messages found.");
-            }
-            for (i, message) in messages
-                .iter()
-                .rev()
-                .enumerate()
-            {
-                if i >= message_limit {
-                    break;
-                }
-
-                ui.separator();
-                if message.is_sender_npc() {
-                    // Probably an NPC, won't have a pirate page to go to
-                    append_npc_chat_line(message, ui);
-                } else {
-                    append_player_chat_line(message, ui);
-                }
-            }
-            return;
-        }
-
-        let messages = match chat_type {
-            ChatType::Chat => &parsed_stuff.chat_messages,
-            ChatType::Trade => &parsed_stuff.trade_chat_messages,
-            ChatType::Global => &parsed_stuff.global_chat_messages,
-            ChatType::Tell => &parsed_stuff.tells,
-            ChatType::All => panic!("Shouldn't have reached here"),
-        };
-
-        if messages.is_empty() {
-            ui.label("No chat messages found.");
-        }
-
-        for (i, message) in messages.iter().rev().enumerate() {
-            if i >= message_limit {
-                break;
-            }
-
-            ui.separator();
-            if message.is_sender_npc() {
-                // Probably an NPC, won't have a pirate page to go to
-                append_npc_chat_line(message, ui);
-            } else {
-                append_player_chat_line(message, ui);
-            }
-        }
-    });
-}
-
-fn append_npc_chat_line(message: &Message, ui: &mut Ui) {
-    let npc_name_color = egui::Color32::from_hex("#FF4500").unwrap();
-    ui.horizontal_top(|ui| {
-        ui.spacing_mut().item_spacing.x = 0.0;
-        ui.label(message.timestamp_from_message());
-        ui.label(" ");
-        ui.label(egui::RichText::new(&message.sender).color(npc_name_color));
-        ui.add(egui::Label::new(message.contents_without_sender()).wrap(true));
-    });
-}
-
-fn append_player_chat_line(message: &Message, ui: &mut Ui) {
-    ui.horizontal_top(|ui| {
-        ui.spacing_mut().item_spacing.x = 0.0;
-        ui.label(message.timestamp_from_message());
-        ui.label(" ");
-        ui.hyperlink_to(
-            &message.sender,
-            PIRATE_INFO_URL.to_owned() + &message.sender,
-        );
-        ui.add(egui::Label::new(message.contents_without_sender()).wrap(true));
-    });
-}
-
-fn greedy_ui(ui: &mut Ui, parsed_stuff: &ParsedChatLog) {
-    egui::ScrollArea::vertical().show(ui, |ui| {
-        if parsed_stuff.battles.is_empty() {
-            ui.label("No battles detected.");
-        } else {
-            ui.heading("Greedy hits");
-            for battle in &parsed_stuff.battles {
-                ui.separator();
-                ui.heading(format!(
-                    "Battle between {} and {}",
-                    battle.attacker_ship, battle.defender_ship
-                ));
-                let greedy_count: u32 = battle.greedies.values().sum();
-                let total_greedy_hits_str = format!("{} Greedies in total", greedy_count);
-                ui.label(&total_greedy_hits_str);
-                if battle.greedies.is_empty() {
-                    ui.label("No Greedies for this battle");
-                } else {
-                    let mut sorted_results: Vec<(&String, &u32)> = battle.greedies.iter().collect();
-                    sorted_results.sort_by(|a, b| b.1.cmp(a.1));
-
-                    let mut greedy_clipboard_text = String::new();
-                    greedy_clipboard_text.push_str(&total_greedy_hits_str);
-                    greedy_clipboard_text += ". ";
-
-                    for (i, entry) in sorted_results.iter().enumerate() {
-                        let s = if i == sorted_results.len() - 1 {
-                            format!("{}: {}", entry.0, entry.1)
-                        } else {
-                            format!("{}: {}, ", entry.0, entry.1)
-                        };
-                        greedy_clipboard_text.push_str(&s);
-                    }
-
-                    if ui.button("Copy me!").clicked() {
-                        ui.output_mut(|o| o.copied_text = greedy_clipboard_text);
-                    }
+#[cfg(all(not(windows), not(target_arch = "wasm32")))]
+fn attach_to_parent_console() {}
 
-                    for entry in &sorted_results {
-                        ui.label(format!("{} got {}", entry.0, entry.1));
-                    }
-                }
-            }
-        }
+/// The web build has no chat log on disk to watch, chat logs are uploaded through the page and
+/// parsed once.
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    let web_options = eframe::WebOptions {
+        follow_system_theme: false,
+        ..Default::default()
+    };
+    wasm_bindgen_futures::spawn_local(async {
+        eframe::WebRunner::new()
+            .start(
+                "chat_tracker_canvas",
+                web_options,
+                Box::new(|cc| {
+                    let config = Config::default();
+                    config.appearance.apply(&cc.egui_ctx);
+                    return Box::new(TrackerApp::new(
+                        Arc::new(Mutex::new(config)),
+                        WindowAlerts::default(),
+                    ));
+                }),
+            )
+            .await
+            .expect("Failed to start the chat tracker");
     });
 }
 
-fn open_chat_log(path: &Path) -> BufReader<File> {
-    let file = File::open(path).unwrap();
-    return BufReader::new(file);
-}
-
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
 enum Tabs {
     GreedyHits,
     Chat(ChatType),
+    /// Messages naming the user's pirates
+    Mentions,
     SearchChat,
+    /// Index into the config's saved searches
+    SavedSearch(usize),
     Settings,
 }
 
-#[derive(PartialEq, Copy, Clone)]
+/// The tabs in the order they're shown.
+const TABS: [Tabs; 9] = [
+    Tabs::Chat(ChatType::All),
+    Tabs::Chat(ChatType::Chat),
+    Tabs::Chat(ChatType::Trade),
+    Tabs::Chat(ChatType::Global),
+    Tabs::Chat(ChatType::Tell),
+    Tabs::Mentions,
+    Tabs::SearchChat,
+    Tabs::GreedyHits,
+    Tabs::Settings,
+];
+
+impl Tabs {
+    fn label(&self) -> &'static str {
+        return match self {
+            Tabs::Chat(ChatType::All) => "All chat",
+            Tabs::Chat(ChatType::Chat) => "Chat",
+            Tabs::Chat(ChatType::Trade) => "Trade chat",
+            Tabs::Chat(ChatType::Global) => "Global chat",
+            Tabs::Chat(ChatType::Tell) => "Tells",
+            Tabs::Mentions => "Mentions",
+            Tabs::SearchChat => "Search chat",
+            Tabs::SavedSearch(_) => "Saved search",
+            Tabs::GreedyHits => "Greedies",
+            Tabs::Settings => "Settings",
+        };
+    }
+}
+
+#[derive(
+    PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug, Default, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
 enum ChatType {
     Chat,
     Trade,
     Global,
     Tell,
+    #[default]
     All,
 }
 
-fn write_config_to_config_file(config: &Config, config_path: &Path) -> Result<(), std::io::Error> {
-    let mut file = File::create(config_path)?;
-    let toml = toml::to_string(&config).unwrap();
-    file.write_all(&toml.as_bytes())?;
-    Ok(())
+impl ChatType {
+    /// Short lowercase name, used on the command line and in exported data.
+    fn key(&self) -> &'static str {
+        return match self {
+            ChatType::Chat => "chat",
+            ChatType::Trade => "trade",
+            ChatType::Global => "global",
+            ChatType::Tell => "tell",
+            ChatType::All => "all",
+        };
+    }
+
+    fn from_key(key: &str) -> Option<ChatType> {
+        return match key.to_lowercase().as_str() {
+            "chat" => Some(ChatType::Chat),
+            "trade" => Some(ChatType::Trade),
+            "global" => Some(ChatType::Global),
+            "tell" | "tells" => Some(ChatType::Tell),
+            "all" => Some(ChatType::All),
+            _ => None,
+        };
+    }
 }
@@ -0,0 +1,614 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::path::PathBuf;
+
+use egui::text::{CCursor, LayoutJob};
+use egui::{Rect, Sense, TextFormat, Ui};
+
+use crate::alerts::WindowAlerts;
+use crate::appearance::Appearance;
+use crate::chat_log::ParsedChatLog;
+use crate::mutes::Mutes;
+use crate::npcs::Npcs;
+use crate::Message;
+
+/// How lists of messages are shown.
+#[derive(Clone, Copy)]
+pub struct MessageList<'a> {
+    /// How many messages to show at a time. Older ones can be loaded a page at a time.
+    pub message_limit: usize,
+    pub window_alerts: &'a WindowAlerts,
+    pub mutes: &'a Mutes,
+    pub show_muted: bool,
+    pub npcs: &'a Npcs,
+    pub appearance: &'a Appearance,
+    /// Leave out messages from NPCs
+    pub hide_npcs: bool,
+    /// Where the user had read up to. A line goes between the messages after it and the rest.
+    pub new_messages_after: Option<u32>,
+    /// Messages are given newest first, but shown oldest first. The list follows new messages
+    /// until the user scrolls up.
+    pub newest_at_bottom: bool,
+    /// Goes up with each snapshot, and whenever the ignore list, mute rules or NPCs change
+    pub version: u64,
+}
+
+/// Which messages a list shows, and how many of them are muted. Only worked out again when
+/// something's changed, rather than every frame.
+#[derive(Default)]
+pub struct ListedMessages {
+    /// The list version, and whether NPCs and muted messages were being hidden
+    built_for: Option<(u64, bool, bool)>,
+    /// Ids of the messages to show in order, with any parts of them to highlight
+    messages: Vec<(u32, Vec<Range<usize>>)>,
+    /// Whether they're shown or not
+    muted: usize,
+}
+
+impl ListedMessages {
+    /// Finds the messages with `find` again if anything's changed since they were last found.
+    pub fn update<'a>(
+        &mut self,
+        list: &MessageList,
+        find: impl FnOnce() -> Vec<(&'a Message, Vec<Range<usize>>)>,
+    ) {
+        let built_for = Some((list.version, list.hide_npcs, list.show_muted));
+        if self.built_for == built_for {
+            return;
+        }
+        self.built_for = built_for;
+        self.messages.clear();
+        self.muted = 0;
+        for (message, highlights) in find() {
+            if list.hide_npcs && message.is_sender_npc(list.npcs) {
+                continue;
+            }
+            if list.mutes.is_muted(message) {
+                self.muted += 1;
+                if !list.show_muted {
+                    continue;
+                }
+            }
+            self.messages.push((message.id, highlights));
+        }
+    }
+}
+
+/// Kept between frames so lists don't have to be laid out in full every frame.
+#[derive(Default)]
+pub struct MessageListState {
+    /// Width, text size and font the heights were measured with
+    measured_with: Option<(f32, egui::FontId, Option<PathBuf>)>,
+    /// Of messages that have been on screen, by id. Others are guessed to be one line.
+    heights: HashMap<u32, f32>,
+    /// The list version when messages that have gone were last forgotten
+    heights_version: u64,
+    /// By scroll area, the first row on screen and how far down the list it was
+    anchors: HashMap<egui::Id, (RowKey, f32)>,
+    /// By scroll area, how many pages of messages have been loaded. One if not given.
+    pages: HashMap<egui::Id, usize>,
+    /// The pirate whose name was last clicked, waiting for their profile to be shown
+    pub clicked_pirate: Option<String>,
+}
+
+/// A message with a particular set of highlights, the new messages line or the load older button.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+enum RowKey {
+    Message { id: u32, highlights: u64 },
+    NewMessages,
+    LoadOlder,
+}
+
+enum Row<'a> {
+    /// By id
+    Message(u32, &'a [Range<usize>]),
+    NewMessages,
+    /// There's more history than has been loaded
+    LoadOlder,
+}
+
+impl Row<'_> {
+    fn key(&self) -> RowKey {
+        return match self {
+            Row::Message(id, highlights) => {
+                let mut hasher = DefaultHasher::new();
+                highlights.hash(&mut hasher);
+                RowKey::Message {
+                    id: *id,
+                    highlights: hasher.finish(),
+                }
+            }
+            Row::NewMessages => RowKey::NewMessages,
+            Row::LoadOlder => RowKey::LoadOlder,
+        };
+    }
+}
+
+/// The listed messages in order, with any highlighted parts of them, a page at a time. Only the
+/// rows on screen are laid out, and what's on screen stays put as messages come in or older ones
+/// are loaded.
+pub fn messages_ui(
+    ui: &mut Ui,
+    id_source: impl Hash,
+    parsed_stuff: &ParsedChatLog,
+    listed: &ListedMessages,
+    list: &MessageList,
+    state: &mut MessageListState,
+) {
+    let muted = listed.muted;
+    if muted > 0 {
+        let note = if list.show_muted {
+            format!("Showing {} muted messages", muted)
+        } else {
+            format!(
+                "{} messages hidden by your ignore list and mute rules",
+                muted
+            )
+        };
+        ui.label(egui::RichText::new(note).weak().small());
+    }
+    let id_source = egui::Id::new(id_source);
+    let scroll_id = ui.make_persistent_id(id_source);
+    let pages = state.pages.get(&scroll_id).copied().unwrap_or(1);
+    let rows = page_rows(
+        &listed.messages,
+        list.message_limit.saturating_mul(pages),
+        list,
+    );
+    if rows.is_empty() {
+        ui.label("No chat messages found.");
+        return;
+    }
+
+    let width = ui.available_width();
+    let font_id = egui::TextStyle::Body.resolve(ui.style());
+    let measured_with = Some((width, font_id.clone(), list.appearance.custom_font.clone()));
+    if state.measured_with != measured_with {
+        state.heights.clear();
+        state.measured_with = measured_with;
+    }
+    if state.heights_version != list.version {
+        // Messages that aren't in the chat log any more won't be shown again
+        state
+            .heights
+            .retain(|id, _| parsed_stuff.message_with_id(*id).is_some());
+        state.heights_version = list.version;
+    }
+    let padding = ui.spacing().item_spacing.y;
+    let one_line = ui.fonts(|fonts| fonts.row_height(&font_id)) + padding * 2.0;
+    let button_row = ui.spacing().interact_size.y + padding * 2.0;
+    let heights: Vec<f32> = rows
+        .iter()
+        .map(|row| match row {
+            Row::Message(id, _) => *state.heights.get(id).unwrap_or(&one_line),
+            Row::NewMessages => one_line,
+            Row::LoadOlder => button_row,
+        })
+        .collect();
+    let tops = row_tops(&heights);
+    let total_height = tops.last().unwrap() + heights.last().unwrap();
+
+    let mut scroll_area = egui::ScrollArea::vertical()
+        .id_source(id_source)
+        .auto_shrink(false)
+        .stick_to_bottom(list.newest_at_bottom);
+    let offset = egui::scroll_area::State::load(ui.ctx(), scroll_id)
+        .map(|scroll| scroll.offset.y)
+        .unwrap_or(0.0);
+    // At the top the newest messages stay in view, otherwise what was being read does. Newest at
+    // the bottom, sticking to the bottom does the same.
+    if offset > 0.0 {
+        if let Some(anchor) = state.anchors.get(&scroll_id) {
+            let keys: Vec<RowKey> = rows.iter().map(|row| row.key()).collect();
+            if let Some(moved_by) = anchor_moved_by(anchor, &keys, &tops) {
+                scroll_area = scroll_area.vertical_scroll_offset((offset + moved_by).max(0.0));
+            }
+        }
+    }
+
+    scroll_area.show_viewport(ui, |ui, viewport| {
+        ui.set_height(total_height);
+        let origin = ui.max_rect().min;
+        let width = ui.max_rect().width();
+        // The last row starting above the top of the screen is partly on it
+        let first = tops
+            .partition_point(|top| *top <= viewport.min.y)
+            .saturating_sub(1);
+        let mut remeasured = false;
+        let mut anchor = None;
+        for i in first..rows.len() {
+            if tops[i] > viewport.max.y {
+                break;
+            }
+            let rect = Rect::from_min_size(
+                origin + egui::vec2(0.0, tops[i]),
+                egui::vec2(width, heights[i]),
+            );
+            let height = match rows[i] {
+                Row::Message(id, highlights) => match parsed_stuff.message_with_id(id) {
+                    Some(message) => message_row_ui(
+                        ui,
+                        rect,
+                        message,
+                        highlights,
+                        list,
+                        &mut state.clicked_pirate,
+                    ),
+                    // Gone with a different chat log, the list's about to be worked out again
+                    None => one_line,
+                },
+                Row::NewMessages => {
+                    // Pointing at the new messages
+                    let text = if list.newest_at_bottom {
+                        "▼ New messages"
+                    } else {
+                        "▲ New messages"
+                    };
+                    ui.painter().text(
+                        rect.left_center(),
+                        egui::Align2::LEFT_CENTER,
+                        text,
+                        font_id.clone(),
+                        ui.visuals().warn_fg_color,
+                    );
+                    one_line
+                }
+                Row::LoadOlder => {
+                    let button =
+                        egui::Button::new(format!("Load {} older messages", list.message_limit));
+                    if ui
+                        .put(rect.shrink2(egui::vec2(0.0, padding)), button)
+                        .clicked()
+                    {
+                        state.pages.insert(scroll_id, pages + 1);
+                    }
+                    button_row
+                }
+            };
+            ui.painter().hline(
+                rect.x_range(),
+                rect.top() + height,
+                ui.visuals().widgets.noninteractive.bg_stroke,
+            );
+            if let Row::Message(id, _) = rows[i] {
+                state.heights.insert(id, height);
+            }
+            remeasured |= height != heights[i];
+            if anchor.is_none() && tops[i] + height > viewport.min.y {
+                anchor = Some((rows[i].key(), tops[i]));
+            }
+        }
+        if let Some(anchor) = anchor {
+            state.anchors.insert(scroll_id, anchor);
+        }
+        // Rows below ones that turned out taller or shorter than guessed need moving
+        if remeasured {
+            ui.ctx().request_repaint();
+        }
+    });
+}
+
+/// Where each row starts, going down the list.
+fn row_tops(heights: &[f32]) -> Vec<f32> {
+    let mut tops = Vec::with_capacity(heights.len());
+    let mut top = 0.0;
+    for height in heights {
+        tops.push(top);
+        top += height;
+    }
+    return tops;
+}
+
+/// How far the anchored row has moved down the list since it was last on screen, if it's still
+/// in the list.
+fn anchor_moved_by(anchor: &(RowKey, f32), keys: &[RowKey], tops: &[f32]) -> Option<f32> {
+    let (key, old_top) = anchor;
+    let i = keys.iter().position(|row_key| row_key == key)?;
+    let moved_by = tops[i] - old_top;
+    if moved_by == 0.0 {
+        return None;
+    }
+    return Some(moved_by);
+}
+
+/// Paints a message as one block of text, and returns how tall it was. Clicking a pirate's name
+/// asks for their profile.
+fn message_row_ui(
+    ui: &mut Ui,
+    rect: Rect,
+    message: &Message,
+    highlights: &[Range<usize>],
+    list: &MessageList,
+    clicked_pirate: &mut Option<String>,
+) -> f32 {
+    let padding = ui.spacing().item_spacing.y;
+    let is_npc = message.is_sender_npc(list.npcs);
+    let (job, sender) = chat_line_job(message, highlights, is_npc, list.appearance, ui);
+    let mut job = job;
+    job.wrap.max_width = rect.width();
+    let galley = ui.fonts(|fonts| fonts.layout_job(job));
+    let height = galley.size().y + padding * 2.0;
+    let row_rect = Rect::from_min_size(rect.min, egui::vec2(rect.width(), height));
+
+    if list.window_alerts.is_highlighted(message.id) {
+        let fill = ui.visuals().selection.bg_fill.gamma_multiply(0.4);
+        ui.painter().rect_filled(row_rect, 0.0, fill);
+    }
+    let text_pos = rect.min + egui::vec2(0.0, padding);
+    let sender_rect = Rect::from_min_max(
+        galley.pos_from_ccursor(CCursor::new(sender.start)).min,
+        galley.pos_from_ccursor(CCursor::new(sender.end)).max,
+    )
+    .translate(text_pos.to_vec2());
+    ui.painter()
+        .galley(text_pos, galley, ui.visuals().text_color());
+
+    // NPCs don't have a profile to show
+    if !is_npc {
+        let id = ui.id().with(("sender", message.id));
+        let response = ui.interact(sender_rect, id, Sense::click());
+        if response.hovered() {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+        }
+        if response.clicked() {
+            *clicked_pirate = Some(message.sender.clone());
+        }
+        response.on_hover_text(format!(
+            "Show {}'s messages, stats and notes",
+            message.sender
+        ));
+    }
+    return height;
+}
+
+/// The whole message as it's shown: the sender in NPC or link colour, then what was said in its
+/// chat's colour with any highlighted parts picked out. Also gives where the sender is, in chars.
+fn chat_line_job(
+    message: &Message,
+    highlights: &[Range<usize>],
+    is_npc: bool,
+    appearance: &Appearance,
+    ui: &Ui,
+) -> (LayoutJob, Range<usize>) {
+    let contents = &message.contents;
+    let (sender_start, text_start) = message.sender_indexes();
+    let format = TextFormat {
+        font_id: egui::TextStyle::Body.resolve(ui.style()),
+        color: ui.visuals().text_color(),
+        ..Default::default()
+    };
+    let sender_color = if is_npc {
+        appearance.npc_name_colour.color32()
+    } else {
+        ui.visuals().hyperlink_color
+    };
+    let text_format = TextFormat {
+        color: appearance
+            .chat_type_colour(message.chat_type)
+            .map(|colour| colour.color32())
+            .unwrap_or(ui.visuals().text_color()),
+        ..format.clone()
+    };
+    let highlighted_format = TextFormat {
+        background: ui.visuals().selection.bg_fill,
+        color: ui.visuals().selection.stroke.color,
+        ..format.clone()
+    };
+
+    let mut job = LayoutJob::default();
+    job.append(&contents[..sender_start], 0.0, format.clone());
+    job.append(
+        &contents[sender_start..text_start],
+        0.0,
+        TextFormat {
+            color: sender_color,
+            ..format
+        },
+    );
+    let mut position = text_start;
+    for highlight in highlights {
+        // Only what was said gets highlighted
+        let start = highlight.start.max(position);
+        let end = highlight.end.min(contents.len());
+        if start >= end {
+            continue;
+        }
+        job.append(&contents[position..start], 0.0, text_format.clone());
+        job.append(&contents[start..end], 0.0, highlighted_format.clone());
+        position = end;
+    }
+    job.append(&contents[position..], 0.0, text_format);
+
+    let sender_start_char = contents[..sender_start].chars().count();
+    let sender_chars = sender_start_char..sender_start_char + message.sender.chars().count();
+    return (job, sender_chars);
+}
+
+/// The loaded messages, newest first unless the list shows them at the bottom, with a line
+/// where the new messages start and a button for more if there are any.
+fn page_rows<'a>(
+    messages: &'a [(u32, Vec<Range<usize>>)],
+    loaded: usize,
+    list: &MessageList,
+) -> Vec<Row<'a>> {
+    let more_to_load = messages.len() > loaded;
+    let mut rows = vec![];
+    let mut reached_read_messages = false;
+    for (i, (id, highlights)) in messages.iter().take(loaded).enumerate() {
+        if let Some(last_read) = list.new_messages_after {
+            if !reached_read_messages && *id <= last_read {
+                reached_read_messages = true;
+                // Only worth a line if there's something new above it
+                if i > 0 {
+                    rows.push(Row::NewMessages);
+                }
+            }
+        }
+        rows.push(Row::Message(*id, highlights));
+    }
+    if more_to_load {
+        rows.push(Row::LoadOlder);
+    }
+    if list.newest_at_bottom {
+        rows.reverse();
+    }
+    return rows;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use super::{anchor_moved_by, page_rows, row_tops, ListedMessages, MessageList, Row, RowKey};
+    use crate::alerts::WindowAlerts;
+    use crate::appearance::Appearance;
+    use crate::chat_log::ParsedChatLog;
+    use crate::mutes::Mutes;
+    use crate::npcs::Npcs;
+
+    #[test]
+    fn test_rows_stay_put() {
+        let heights = [10.0, 20.0, 10.0];
+        let tops = row_tops(&heights);
+        assert_eq!(tops, vec![0.0, 10.0, 30.0]);
+
+        let reading = RowKey::Message {
+            id: 5,
+            highlights: 0,
+        };
+        let keys = [
+            RowKey::Message {
+                id: 7,
+                highlights: 0,
+            },
+            RowKey::NewMessages,
+            reading,
+        ];
+        // Two rows came in above the one being read, so the scroll moves down with it
+        assert_eq!(anchor_moved_by(&(reading, 0.0), &keys, &tops), Some(30.0));
+        assert_eq!(anchor_moved_by(&(reading, 30.0), &keys, &tops), None);
+        // Gone from the list, nothing to keep in place
+        let gone = RowKey::Message {
+            id: 1,
+            highlights: 0,
+        };
+        assert_eq!(anchor_moved_by(&(gone, 0.0), &keys, &tops), None);
+    }
+
+    #[test]
+    fn test_pages() {
+        let log = "[16:05:04] Bob says, \"one\"
+[16:05:05] Bob says, \"two\"
+[16:05:06] Bob says, \"three\"";
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        let messages: Vec<_> = parsed
+            .chat_messages
+            .iter()
+            .rev()
+            .map(|message| (message.id, vec![]))
+            .collect();
+        let window_alerts = WindowAlerts::default();
+        let mutes = Mutes::default();
+        let npcs = Npcs::new(&[]);
+        let appearance = Appearance::default();
+        let mut list = MessageList {
+            message_limit: 2,
+            window_alerts: &window_alerts,
+            mutes: &mutes,
+            show_muted: false,
+            npcs: &npcs,
+            appearance: &appearance,
+            hide_npcs: false,
+            new_messages_after: Some(2),
+            newest_at_bottom: false,
+            version: 0,
+        };
+        let keys = |loaded, list: &MessageList| -> Vec<RowKey> {
+            return page_rows(&messages, loaded, list)
+                .iter()
+                .map(|row| match row {
+                    Row::Message(id, _) => RowKey::Message {
+                        id: *id,
+                        highlights: 0,
+                    },
+                    _ => row.key(),
+                })
+                .collect();
+        };
+        let message = |id| RowKey::Message { id, highlights: 0 };
+
+        assert_eq!(
+            keys(2, &list),
+            vec![
+                message(3),
+                RowKey::NewMessages,
+                message(2),
+                RowKey::LoadOlder
+            ]
+        );
+        // Everything's loaded, so no button
+        assert_eq!(
+            keys(4, &list),
+            vec![message(3), RowKey::NewMessages, message(2), message(1)]
+        );
+        list.newest_at_bottom = true;
+        assert_eq!(
+            keys(2, &list),
+            vec![
+                RowKey::LoadOlder,
+                message(2),
+                RowKey::NewMessages,
+                message(3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_listed_messages() {
+        let log = "[16:05:04] Bob says, \"one\"
+[16:05:05] Big Barry says, \"two\"
+[16:05:06] Bob says, \"three\"";
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        let window_alerts = WindowAlerts::default();
+        let mutes = Mutes::new(&["Big Barry".to_string()], &[]);
+        let npcs = Npcs::new(&[]);
+        let appearance = Appearance::default();
+        let mut list = MessageList {
+            message_limit: 2,
+            window_alerts: &window_alerts,
+            mutes: &mutes,
+            show_muted: false,
+            npcs: &npcs,
+            appearance: &appearance,
+            hide_npcs: false,
+            new_messages_after: None,
+            newest_at_bottom: false,
+            version: 0,
+        };
+        let find = || {
+            return parsed
+                .chat_messages
+                .iter()
+                .map(|message| (message, vec![]))
+                .collect();
+        };
+        let ids = |listed: &ListedMessages| -> Vec<u32> {
+            return listed.messages.iter().map(|(id, _)| *id).collect();
+        };
+
+        let mut listed = ListedMessages::default();
+        listed.update(&list, find);
+        assert_eq!(ids(&listed), vec![1, 3]);
+        assert_eq!(listed.muted, 1);
+        // Nothing's changed, so nothing's looked for
+        listed.update(&list, || panic!("Found again"));
+        list.show_muted = true;
+        listed.update(&list, find);
+        assert_eq!(ids(&listed), vec![1, 2, 3]);
+        assert_eq!(listed.muted, 1);
+    }
+}
@@ -0,0 +1,101 @@
+use regex::{Regex, RegexBuilder};
+
+use crate::Message;
+
+/// Hides messages from ignored pirates, and messages matching a mute rule.
+pub struct Mutes {
+    ignored_pirates: Vec<String>,
+    mute_rules: Vec<String>,
+    /// The rules that compiled. Blank rules are left out, they'd mute everything.
+    regexes: Vec<Regex>,
+    /// Lines up with the mute rules
+    errors: Vec<Option<String>>,
+}
+
+impl Mutes {
+    /// Mute rules are regular expressions matched against the whole line, ignoring case.
+    pub fn new(ignored_pirates: &[String], mute_rules: &[String]) -> Mutes {
+        let mut regexes = vec![];
+        let mut errors = vec![];
+        for rule in mute_rules {
+            if rule.trim().is_empty() {
+                errors.push(None);
+                continue;
+            }
+            match RegexBuilder::new(rule).case_insensitive(true).build() {
+                Ok(regex) => {
+                    regexes.push(regex);
+                    errors.push(None);
+                }
+                Err(e) => errors.push(Some(e.to_string())),
+            }
+        }
+        return Mutes {
+            ignored_pirates: ignored_pirates.to_vec(),
+            mute_rules: mute_rules.to_vec(),
+            regexes,
+            errors,
+        };
+    }
+
+    /// Whether this was built from the same lists, so doesn't need building again.
+    pub fn is_for(&self, ignored_pirates: &[String], mute_rules: &[String]) -> bool {
+        return self.ignored_pirates == ignored_pirates && self.mute_rules == mute_rules;
+    }
+
+    /// Why a mute rule couldn't be used, ready to show to the user.
+    pub fn rule_error(&self, i: usize) -> Option<&str> {
+        return self.errors.get(i).and_then(|e| e.as_deref());
+    }
+
+    pub fn is_muted(&self, message: &Message) -> bool {
+        let ignored = self
+            .ignored_pirates
+            .iter()
+            .any(|name| name.trim().eq_ignore_ascii_case(&message.sender));
+        return ignored
+            || self
+                .regexes
+                .iter()
+                .any(|regex| regex.is_match(&message.contents));
+    }
+}
+
+impl Default for Mutes {
+    fn default() -> Self {
+        return Mutes::new(&[], &[]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use super::Mutes;
+    use crate::chat_log::ParsedChatLog;
+
+    #[test]
+    fn test_mutes() {
+        let log = "[16:05:04] Spammer trade chats, \"Buying everything\"
+[16:05:05] Bob trade chats, \"WTS iron\"
+[16:05:06] Bob trade chats, \"Selling iron\"";
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        let messages = &parsed.trade_chat_messages;
+
+        let ignored = vec!["spammer ".to_string()];
+        let rules = vec!["^.*wts".to_string(), "".to_string(), "(iron".to_string()];
+        let mutes = Mutes::new(&ignored, &rules);
+        assert!(mutes.is_muted(&messages[0]));
+        assert!(mutes.is_muted(&messages[1]));
+        // The blank and broken rules don't mute anything
+        assert!(!mutes.is_muted(&messages[2]));
+        assert_eq!(mutes.rule_error(0), None);
+        assert_eq!(mutes.rule_error(1), None);
+        assert!(mutes.rule_error(2).unwrap().contains("unclosed group"));
+
+        assert!(mutes.is_for(&ignored, &rules));
+        assert!(!mutes.is_for(&ignored, &[]));
+        assert!(!Mutes::default().is_muted(&messages[0]));
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::HashSet;
+
+/// NPCs whose names are only one word, so look like pirates. One name per line, `#` comments.
+const BUNDLED_NPCS: &str = include_str!("known_npcs.txt");
+
+/// Tells NPCs from pirates by name.
+pub struct Npcs {
+    /// As given in the config, on top of the bundled ones
+    extra_names: Vec<String>,
+    /// Lowercase
+    names: HashSet<String>,
+}
+
+impl Npcs {
+    pub fn new(extra_names: &[String]) -> Npcs {
+        let bundled = BUNDLED_NPCS
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'));
+        let names = bundled
+            .chain(extra_names.iter().map(|name| name.as_str()))
+            .map(|name| name.trim().to_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect();
+        return Npcs {
+            extra_names: extra_names.to_vec(),
+            names,
+        };
+    }
+
+    /// Whether this was built from the same names, so doesn't need building again.
+    pub fn is_for(&self, extra_names: &[String]) -> bool {
+        return self.extra_names == extra_names;
+    }
+
+    /// Players can't have whitespace in their names, but NPCs can. Single word NPCs have to be
+    /// known.
+    pub fn is_npc(&self, name: &str) -> bool {
+        return name.split_whitespace().count() > 1 || self.names.contains(&name.to_lowercase());
+    }
+}
+
+impl Default for Npcs {
+    fn default() -> Self {
+        return Npcs::new(&[]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Npcs;
+
+    #[test]
+    fn test_is_npc() {
+        let npcs = Npcs::new(&[" Gossip ".to_string(), "".to_string()]);
+        assert!(npcs.is_npc("Big Barry"));
+        assert!(npcs.is_npc("Brigand"));
+        assert!(npcs.is_npc("gossip"));
+        assert!(!npcs.is_npc("Bob"));
+
+        assert!(npcs.is_for(&[" Gossip ".to_string(), "".to_string()]));
+        assert!(!npcs.is_for(&[]));
+        assert!(!Npcs::default().is_npc("Gossip"));
+    }
+}
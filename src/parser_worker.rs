@@ -0,0 +1,513 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use notify::RecommendedWatcher;
+
+use crate::archive::Archive;
+use crate::chat_log::{looks_like_chat_log, ChatLogUpdate, ParsedChatLog};
+use crate::file_watch::watch_file;
+use crate::{ChatType, Config, DEFAULT_ARCHIVE_DIR};
+
+/// What the parser thread can be asked to do.
+pub enum ParserCommand {
+    /// Start tracking a chat log, picking up from its archive so only what's new needs parsing
+    Open {
+        chat_log_path: PathBuf,
+        archive: Archive,
+    },
+    /// Read the whole chat log again. The archive keeps history the log no longer has, and
+    /// anything we've already got is skipped.
+    Reload,
+    /// How long to wait between checks for new lines, when the chat log can't be watched
+    SetInterval(Duration),
+    /// Stop checking for new lines until unpaused. Opening and reloading still work.
+    Pause(bool),
+    /// Read positions changed by the frontend, to be archived
+    SaveReadPositions(BTreeMap<String, u32>),
+}
+
+/// What wakes the parser thread up.
+enum Input {
+    Command(ParserCommand),
+    /// Sent by the filesystem watcher
+    ChatLogChanged,
+}
+
+/// What the parser thread sends back.
+enum ParserEvent {
+    /// New lines read so far in a long parse
+    Progress(usize),
+    Error(String),
+    /// A copy of everything parsed from a newly opened chat log
+    Opened(Box<ParsedChatLog>),
+    /// What's new since the last Opened or Update
+    Update(ChatLogUpdate),
+}
+
+impl ParserCommand {
+    /// Opens the chat log in the config, if there is one.
+    pub fn open(config: &Config) -> Option<ParserCommand> {
+        let chat_log_path = config.chat_log_path.clone()?;
+        let archive_root = match &config.archive_dir {
+            Some(archive_dir) => archive_dir.clone(),
+            None => PathBuf::from(DEFAULT_ARCHIVE_DIR),
+        };
+        let archive = Archive::for_chat_log(&archive_root, &chat_log_path);
+        return Some(ParserCommand::Open {
+            chat_log_path,
+            archive,
+        });
+    }
+}
+
+/// Parses the chat log on its own thread, so frontends never wait on a parse. Frontends send it
+/// commands and pick up what it's found with `receive`.
+pub struct ParserWorker {
+    inputs: Sender<Input>,
+    events: Receiver<ParserEvent>,
+    /// New lines read so far, while a chat log is being opened or reloaded
+    pub progress: Option<usize>,
+    /// Why the last parse failed, until one works
+    pub error: Option<String>,
+    pub paused: bool,
+    /// The parser thread's gone, nothing more will be parsed
+    pub stopped: bool,
+}
+
+impl ParserWorker {
+    /// Parses as soon as the opened chat log changes, or every `interval` if changes to it can't
+    /// be watched for. `on_parse` is called on the parser thread whenever something new was
+    /// parsed, told whether it's from a newly opened chat log, and `wake` whenever there's
+    /// something to receive.
+    pub fn spawn(
+        interval: Duration,
+        on_parse: impl FnMut(&ParsedChatLog, bool) + Send + 'static,
+        wake: impl Fn() + Send + 'static,
+    ) -> ParserWorker {
+        let (inputs, input_receiver) = mpsc::channel();
+        let (event_sender, events) = mpsc::channel();
+        let watcher_inputs = inputs.clone();
+        std::thread::spawn(move || {
+            run(
+                interval,
+                input_receiver,
+                watcher_inputs,
+                event_sender,
+                on_parse,
+                wake,
+            );
+        });
+        return ParserWorker {
+            inputs,
+            events,
+            progress: None,
+            error: None,
+            paused: false,
+            stopped: false,
+        };
+    }
+
+    pub fn send(&mut self, command: ParserCommand) {
+        match &command {
+            ParserCommand::Open { .. } | ParserCommand::Reload => self.progress = Some(0),
+            ParserCommand::Pause(paused) => self.paused = *paused,
+            _ => {}
+        }
+        // Only fails if the parser thread panicked, which has already been reported
+        let _ = self.inputs.send(Input::Command(command));
+    }
+
+    /// Takes in everything the parser thread has sent since last time, bringing `parsed` up to
+    /// date. Read positions are the frontend's, so changes to them are sent back to be archived.
+    /// Returns whether `parsed` changed.
+    pub fn receive(&mut self, parsed: &mut ParsedChatLog) -> bool {
+        let mut changed = false;
+        loop {
+            let event = match self.events.try_recv() {
+                Ok(event) => event,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !self.stopped {
+                        self.stopped = true;
+                        self.progress = None;
+                        self.error = Some(
+                            "Stopped reading the chat log after an unexpected error. Restart the tracker to carry on"
+                                .to_string(),
+                        );
+                    }
+                    break;
+                }
+            };
+            match event {
+                ParserEvent::Progress(lines) => self.progress = Some(lines),
+                ParserEvent::Error(e) => {
+                    self.progress = None;
+                    self.error = Some(e);
+                }
+                ParserEvent::Opened(mut opened) => {
+                    // The archive's read positions are already saved
+                    opened.read_positions_changed = false;
+                    *parsed = *opened;
+                    changed = true;
+                    self.progress = None;
+                    self.error = None;
+                }
+                ParserEvent::Update(update) => {
+                    parsed.apply_update(update);
+                    changed = true;
+                    self.progress = None;
+                    self.error = None;
+                }
+            }
+        }
+        // While a chat log's being opened, read positions are still for the one before it
+        if parsed.read_positions_changed && self.progress.is_none() {
+            let _ = self
+                .inputs
+                .send(Input::Command(ParserCommand::SaveReadPositions(
+                    parsed.read_positions.clone(),
+                )));
+            parsed.read_positions_changed = false;
+        }
+        return changed;
+    }
+}
+
+fn run(
+    mut interval: Duration,
+    inputs: Receiver<Input>,
+    watcher_inputs: Sender<Input>,
+    events: Sender<ParserEvent>,
+    mut on_parse: impl FnMut(&ParsedChatLog, bool),
+    wake: impl Fn(),
+) {
+    let send = |event| {
+        // The frontend's gone, it'll stop sending commands too
+        let _ = events.send(event);
+        wake();
+    };
+    let mut chat_log: Option<(PathBuf, Archive)> = None;
+    let mut parsed = ParsedChatLog::new();
+    // Newest message the frontend has been sent
+    let mut sent_up_to = None;
+    let mut paused = false;
+    let mut last_parse = Instant::now();
+    let mut last_error = None;
+    // Dropping the watcher stops it
+    let mut watcher: Option<RecommendedWatcher> = None;
+    // Set by the watcher until the parse it asked for starts, so a burst of writes only wakes
+    // the parser once
+    let chat_log_changed = Arc::new(AtomicBool::new(false));
+
+    loop {
+        let input = if paused || chat_log.is_none() || watcher.is_some() {
+            match inputs.recv() {
+                Ok(input) => Some(input),
+                Err(_) => return,
+            }
+        } else {
+            match inputs.recv_timeout(interval.saturating_sub(last_parse.elapsed())) {
+                Ok(input) => Some(input),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        };
+        let command = match input {
+            Some(Input::Command(command)) => Some(command),
+            Some(Input::ChatLogChanged) => {
+                chat_log_changed.store(false, Ordering::SeqCst);
+                if paused {
+                    continue;
+                }
+                None
+            }
+            None => None,
+        };
+        // Asked for, so the frontend hears back even if nothing's changed
+        let asked = command.is_some();
+        let mut opened = false;
+        match command {
+            Some(ParserCommand::Open {
+                chat_log_path,
+                archive,
+            }) => {
+                parsed = match archive.load() {
+                    Ok(parsed) => parsed,
+                    Err(e) => {
+                        eprintln!("Couldn't load the chat archive: {}", e);
+                        ParsedChatLog::new()
+                    }
+                };
+                watcher = match watch(&chat_log_path, &watcher_inputs, &chat_log_changed) {
+                    Ok(watcher) => Some(watcher),
+                    Err(e) => {
+                        eprintln!(
+                            "Couldn't watch the chat log for changes, checking it every {}ms instead: {}",
+                            interval.as_millis(),
+                            e
+                        );
+                        None
+                    }
+                };
+                chat_log = Some((chat_log_path, archive));
+                opened = true;
+            }
+            Some(ParserCommand::Reload) => parsed.restart_from_beginning(),
+            Some(ParserCommand::SetInterval(new_interval)) => {
+                interval = new_interval;
+                continue;
+            }
+            Some(ParserCommand::Pause(pause)) => {
+                paused = pause;
+                // Unpausing catches up on anything missed
+                if paused {
+                    continue;
+                }
+            }
+            Some(ParserCommand::SaveReadPositions(read_positions)) => {
+                parsed.read_positions = read_positions;
+                parsed.read_positions_changed = true;
+                if let Some((_, archive)) = &chat_log {
+                    if let Err(e) = archive.save(&mut parsed) {
+                        eprintln!("Couldn't save to the chat archive: {}", e);
+                    }
+                }
+                continue;
+            }
+            None => {}
+        }
+        let (chat_log_path, archive) = match &chat_log {
+            Some(chat_log) => chat_log,
+            None => continue,
+        };
+
+        let result = update_from_chat_log(&mut parsed, chat_log_path, archive, |lines| {
+            send(ParserEvent::Progress(lines))
+        });
+        last_parse = Instant::now();
+        let changed = *result.as_ref().unwrap_or(&false);
+        if changed || asked {
+            on_parse(&parsed, opened);
+            let newest = parsed.newest_message_id(ChatType::All);
+            if opened {
+                // Everything's in the copy, so the update's only taken to start afresh
+                parsed.take_update(newest);
+                send(ParserEvent::Opened(Box::new(parsed.clone())));
+            } else {
+                send(ParserEvent::Update(parsed.take_update(sent_up_to)));
+            }
+            sent_up_to = newest;
+        }
+        match result {
+            Ok(_) => last_error = None,
+            Err(e) => {
+                if asked || last_error.as_ref() != Some(&e) {
+                    send(ParserEvent::Error(e.clone()));
+                }
+                last_error = Some(e);
+            }
+        }
+    }
+}
+
+/// Sends ChatLogChanged whenever the chat log is written to.
+fn watch(
+    chat_log_path: &Path,
+    inputs: &Sender<Input>,
+    chat_log_changed: &Arc<AtomicBool>,
+) -> notify::Result<RecommendedWatcher> {
+    let inputs = inputs.clone();
+    let chat_log_changed = chat_log_changed.clone();
+    return watch_file(chat_log_path, move || {
+        if !chat_log_changed.swap(true, Ordering::SeqCst) {
+            let _ = inputs.send(Input::ChatLogChanged);
+        }
+    });
+}
+
+/// Why the file at `path` can't be tracked as a chat log, if it can't.
+pub fn check_chat_log(path: &Path) -> Result<(), String> {
+    let file = open_chat_log(path)?;
+    if !looks_like_chat_log(BufReader::new(file)) {
+        return Err(format!(
+            "{} doesn't look like a Puzzle Pirates chat log",
+            path.to_string_lossy()
+        ));
+    }
+    return Ok(());
+}
+
+fn open_chat_log(path: &Path) -> Result<File, String> {
+    return File::open(path).map_err(|e| {
+        format!(
+            "Couldn't open chat log at {}: {}",
+            path.to_string_lossy(),
+            e
+        )
+    });
+}
+
+/// Parses anything new in the chat log, and archives it along with any new read positions.
+/// Returns whether there was anything new.
+fn update_from_chat_log(
+    parsed: &mut ParsedChatLog,
+    chat_log_path: &Path,
+    archive: &Archive,
+    mut on_progress: impl FnMut(usize),
+) -> Result<bool, String> {
+    let file = open_chat_log(chat_log_path)?;
+    let place_before = (parsed.last_line_read, parsed.total_lines_read);
+    if !parsed.parse_chat_log_with_progress(BufReader::new(file), &mut on_progress) {
+        // Cut short, so read what's there now rather than waiting for it to change again
+        let file = open_chat_log(chat_log_path)?;
+        parsed.parse_chat_log_with_progress(BufReader::new(file), on_progress);
+    }
+
+    let changed = (parsed.last_line_read, parsed.total_lines_read) != place_before;
+    if changed || parsed.read_positions_changed {
+        if let Err(e) = archive.save(parsed) {
+            eprintln!("Couldn't save to the chat archive: {}", e);
+        }
+    }
+    return Ok(changed);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+
+    use super::{check_chat_log, update_from_chat_log, ParserCommand, ParserWorker};
+    use crate::archive::Archive;
+    use crate::chat_log::ParsedChatLog;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "chat-tracker-parser-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        return dir;
+    }
+
+    /// Waits for the parser thread to send something new
+    fn receive(worker: &mut ParserWorker, parsed: &mut ParsedChatLog) {
+        let started = Instant::now();
+        while !worker.receive(parsed) {
+            assert!(started.elapsed() < Duration::from_secs(10));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_parser_worker() {
+        let dir = temp_dir("worker");
+        let chat_log_path = dir.join("chat.txt");
+        std::fs::write(
+            &chat_log_path,
+            "[16:05:04] Someone tells ye, \"2 for spades\"\n",
+        )
+        .unwrap();
+        let archive = Archive::for_chat_log(&dir.join("archive"), &chat_log_path);
+
+        // Long enough that only the watcher or commands cause a parse
+        let mut worker = ParserWorker::spawn(Duration::from_secs(60), |_, _| {}, || {});
+        let mut parsed = ParsedChatLog::new();
+        worker.send(ParserCommand::Open {
+            chat_log_path: chat_log_path.clone(),
+            archive,
+        });
+        assert_eq!(worker.progress, Some(0));
+        receive(&mut worker, &mut parsed);
+        assert_eq!(worker.progress, None);
+        assert_eq!(parsed.tells.len(), 1);
+
+        // Picked up as soon as it's written
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&chat_log_path)
+            .unwrap();
+        file.write_all(b"[16:05:05] Someone tells ye, \"3 for spades\"\n")
+            .unwrap();
+        while parsed.tells.len() < 2 {
+            receive(&mut worker, &mut parsed);
+        }
+
+        // Read positions are kept over snapshots, and archived
+        parsed.mark_read("tell", Some(parsed.tells[0].id));
+        worker.send(ParserCommand::Reload);
+        receive(&mut worker, &mut parsed);
+        assert_eq!(parsed.last_read("tell"), Some(parsed.tells[0].id));
+        assert!(!parsed.read_positions_changed);
+
+        std::fs::remove_file(&chat_log_path).unwrap();
+        worker.send(ParserCommand::Reload);
+        receive(&mut worker, &mut parsed);
+        let started = Instant::now();
+        while worker.error.is_none() {
+            assert!(started.elapsed() < Duration::from_secs(10));
+            std::thread::sleep(Duration::from_millis(10));
+            worker.receive(&mut parsed);
+        }
+        assert!(worker
+            .error
+            .as_ref()
+            .unwrap()
+            .starts_with("Couldn't open chat log"));
+        // What's been parsed is still there
+        assert_eq!(parsed.tells.len(), 2);
+        let archive = Archive::for_chat_log(&dir.join("archive"), &chat_log_path);
+        assert_eq!(
+            archive.load().unwrap().last_read("tell"),
+            Some(parsed.tells[0].id)
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_update_from_cut_short_chat_log() {
+        let dir = temp_dir("cut-short");
+        let chat_log_path = dir.join("chat.txt");
+        let tell = "[16:05:04] Someone tells ye, \"2 for spades\"\n";
+        std::fs::write(&chat_log_path, tell.repeat(3)).unwrap();
+        let archive = Archive::for_chat_log(&dir.join("archive"), &chat_log_path);
+        let mut parsed = ParsedChatLog::new();
+        assert!(update_from_chat_log(&mut parsed, &chat_log_path, &archive, |_| {}).unwrap());
+
+        // Read straight away, not on the next change
+        let other_tell = "[16:05:09] Someone tells ye, \"3 for spades\"\n";
+        std::fs::write(&chat_log_path, format!("{}{}", tell, other_tell)).unwrap();
+        assert!(update_from_chat_log(&mut parsed, &chat_log_path, &archive, |_| {}).unwrap());
+        assert_eq!(parsed.tells.len(), 4);
+        assert_eq!(parsed.last_line_read, 2);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_check_chat_log() {
+        let dir = temp_dir("check");
+        let chat_log_path = dir.join("chat.txt");
+        assert!(check_chat_log(&chat_log_path)
+            .unwrap_err()
+            .starts_with("Couldn't open chat log"));
+        std::fs::write(&chat_log_path, "[package]\nname = \"chat_tracker\"\n").unwrap();
+        assert!(check_chat_log(&chat_log_path)
+            .unwrap_err()
+            .ends_with("doesn't look like a Puzzle Pirates chat log"));
+        std::fs::write(&chat_log_path, "===== 2024/01/06 =====\n").unwrap();
+        assert_eq!(check_chat_log(&chat_log_path), Ok(()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+
+use egui::Ui;
+use time::macros::format_description;
+
+use crate::chat_log::ParsedChatLog;
+use crate::message_list::{messages_ui, ListedMessages, MessageList, MessageListState};
+use crate::{Battle, ChatType, Message, Tabs};
+
+const PIRATE_INFO_URL: &str = "https://emerald.puzzlepirates.com/yoweb/pirate.wm?target=";
+
+/// Everything the chat log says about one pirate. A copy, so it can be kept until there's
+/// something new in the chat log.
+pub struct PirateProfile {
+    pub pirate: String,
+    /// Newest first
+    pub messages: Vec<Message>,
+    /// How many messages they've sent in each chat
+    pub channel_counts: BTreeMap<ChatType, usize>,
+    /// Battles they got greedy hits in and how many, newest first
+    pub greedies: Vec<(Battle, u32)>,
+    /// Which of their messages are shown
+    pub listed: ListedMessages,
+}
+
+impl PirateProfile {
+    pub fn new(parsed_stuff: &ParsedChatLog, pirate: &str) -> Self {
+        let mut messages: Vec<Message> = parsed_stuff
+            .messages_in_order_of_creation()
+            .into_iter()
+            .filter(|message| message.sender == pirate)
+            .cloned()
+            .collect();
+        messages.reverse();
+        let mut channel_counts = BTreeMap::new();
+        for message in &messages {
+            *channel_counts.entry(message.chat_type).or_default() += 1;
+        }
+        let greedies = parsed_stuff
+            .battles
+            .iter()
+            .filter_map(|battle| Some((battle.clone(), *battle.greedies.get(pirate)?)))
+            .collect();
+        return PirateProfile {
+            pirate: pirate.to_string(),
+            messages,
+            channel_counts,
+            greedies,
+            listed: ListedMessages::default(),
+        };
+    }
+
+    pub fn first_seen(&self) -> Option<&Message> {
+        return self.messages.last();
+    }
+
+    pub fn last_seen(&self) -> Option<&Message> {
+        return self.messages.first();
+    }
+}
+
+/// When a message was sent, with the date if the chat log had one by then.
+fn seen_at(message: &Message) -> String {
+    let time_format = format_description!("[hour]:[minute]:[second]");
+    let time = message.timestamp.format(&time_format).unwrap();
+    return match message.date {
+        Some(date) => format!("{} {}", date, time),
+        None => time,
+    };
+}
+
+/// A pirate's stats, greedies and messages, with a note the user can keep about them. Returns
+/// whether it was closed.
+pub fn pirate_profile_ui(
+    ui: &mut Ui,
+    profile: &mut PirateProfile,
+    parsed_stuff: &ParsedChatLog,
+    pirate_notes: &mut BTreeMap<String, String>,
+    list: &MessageList,
+    state: &mut MessageListState,
+) -> bool {
+    let pirate = profile.pirate.as_str();
+    let mut closed = false;
+    ui.horizontal(|ui| {
+        ui.heading(pirate);
+        closed = ui.button("Close").clicked();
+    });
+    ui.hyperlink_to("Pirate page", PIRATE_INFO_URL.to_owned() + pirate);
+
+    if let (Some(first_seen), Some(last_seen)) = (profile.first_seen(), profile.last_seen()) {
+        ui.label(format!("First seen {}", seen_at(first_seen)));
+        ui.label(format!("Last seen {}", seen_at(last_seen)));
+    }
+    for (chat_type, count) in &profile.channel_counts {
+        ui.label(format!(
+            "{}: {} messages",
+            Tabs::Chat(*chat_type).label(),
+            count
+        ));
+    }
+
+    let greedy_count: u32 = profile.greedies.iter().map(|(_, count)| count).sum();
+    egui::CollapsingHeader::new(format!(
+        "{} Greedies in {} battles",
+        greedy_count,
+        profile.greedies.len()
+    ))
+    .show(ui, |ui| {
+        for (battle, count) in &profile.greedies {
+            ui.label(format!(
+                "{} in the battle between {} and {}",
+                count, battle.attacker_ship, battle.defender_ship
+            ));
+        }
+    });
+
+    ui.label("Notes");
+    let mut note = pirate_notes.get(pirate).cloned().unwrap_or_default();
+    if ui.text_edit_multiline(&mut note).changed() {
+        // Nothing's kept for pirates without a note
+        if note.trim().is_empty() {
+            pirate_notes.remove(pirate);
+        } else {
+            pirate_notes.insert(pirate.to_string(), note);
+        }
+    }
+
+    ui.separator();
+    let PirateProfile {
+        pirate,
+        messages,
+        listed,
+        ..
+    } = profile;
+    listed.update(list, || {
+        return messages.iter().map(|message| (message, vec![])).collect();
+    });
+    messages_ui(
+        ui,
+        ("pirate_profile", pirate),
+        parsed_stuff,
+        listed,
+        list,
+        state,
+    );
+    return closed;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use super::PirateProfile;
+    use crate::chat_log::ParsedChatLog;
+    use crate::ChatType;
+
+    #[test]
+    fn test_pirate_profile() {
+        let log = [
+            "[16:05:01] Someone says, \"ahoy\"",
+            "===== 2024/01/06 =====",
+            "[16:05:02] Someone trade chats, \"Buying weavery\"",
+            "[16:05:03] Big Barry says, \"ahoy\"",
+            "[16:05:04] Mean Shad has grappled Shifty Shiner. A melee breaks out between the crews!",
+            "[16:05:05] Someone delivers an overwhelming barrage against Petty Robert, causing some treasure to fall from their grip",
+            "[16:05:06] Someone delivers an overwhelming barrage against Petty Robert, causing some treasure to fall from their grip",
+            "[16:05:07] Big Barry delivers an overwhelming barrage against Petty Robert, causing some treasure to fall from their grip",
+            "[16:05:08] Game Over",
+            "[16:05:09] Someone tells ye, \"2 for spades\"",
+        ]
+        .join("\n");
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+
+        let profile = PirateProfile::new(&parsed, "Someone");
+        assert_eq!(profile.messages.len(), 3);
+        assert_eq!(
+            profile.first_seen().unwrap().contents,
+            "[16:05:01] Someone says, \"ahoy\""
+        );
+        assert_eq!(profile.first_seen().unwrap().date, None);
+        assert_eq!(profile.last_seen().unwrap().chat_type, ChatType::Tell);
+        assert!(profile.last_seen().unwrap().date.is_some());
+        assert_eq!(profile.channel_counts[&ChatType::Chat], 1);
+        assert_eq!(profile.channel_counts[&ChatType::Trade], 1);
+        assert_eq!(profile.channel_counts[&ChatType::Tell], 1);
+        assert!(!profile.channel_counts.contains_key(&ChatType::Global));
+        assert_eq!(profile.greedies.len(), 1);
+        assert_eq!(profile.greedies[0].1, 2);
+
+        let profile = PirateProfile::new(&parsed, "Nobody");
+        assert!(profile.messages.is_empty());
+        assert!(profile.first_seen().is_none());
+        assert!(profile.greedies.is_empty());
+    }
+}
@@ -0,0 +1,331 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use crate::ChatType;
+
+/// Where to find a message in the parsed chat log. Ordered by id, so lists of these are in order
+/// of creation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MessageRef {
+    pub id: u32,
+    pub chat_type: ChatType,
+    pub index: usize,
+}
+
+/// How many letters long the pieces of words are that `grams` are made of, at most.
+const GRAM_LENGTH: usize = 3;
+
+/// Every lowercased word in every message, pointing back at the messages it's in. Gets added to as
+/// messages are parsed, so a search never has to look through every message.
+#[derive(Debug, Default, Clone)]
+pub struct SearchIndex {
+    /// Sorted, so all the words starting with something sit next to each other
+    words: BTreeMap<String, Vec<MessageRef>>,
+    /// Every run of up to GRAM_LENGTH letters in any word, pointing at the words it's in, for
+    /// finding words with something in the middle of them
+    grams: HashMap<String, Vec<String>>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        return SearchIndex {
+            words: BTreeMap::new(),
+            grams: HashMap::new(),
+        };
+    }
+
+    /// Messages have to be added in order of creation.
+    pub fn insert(&mut self, contents: &str, message: MessageRef) {
+        let mut words = words(contents);
+        words.sort();
+        words.dedup();
+        for word in words {
+            let messages = self.words.entry(word.clone()).or_default();
+            if messages.is_empty() {
+                let mut word_grams: Vec<String> = (1..=GRAM_LENGTH)
+                    .flat_map(|length| grams(&word, length))
+                    .collect();
+                word_grams.sort();
+                word_grams.dedup();
+                for gram in word_grams {
+                    self.grams.entry(gram).or_default().push(word.clone());
+                }
+            }
+            messages.push(message);
+        }
+    }
+
+    /// Messages with a word starting with each word of the search term, in order of creation.
+    /// None if the search term doesn't have any words to look up.
+    pub fn search(&self, search_term: &str) -> Option<Vec<MessageRef>> {
+        return self.search_with(search_term, |search_word| {
+            self.messages_with_prefix(search_word)
+        });
+    }
+
+    /// Messages with a word containing each word of the search term, in order of creation. Any
+    /// message with the search term anywhere in it is one of these. None if the search term
+    /// doesn't have any words to look up.
+    pub fn search_anywhere(&self, search_term: &str) -> Option<Vec<MessageRef>> {
+        return self.search_with(search_term, |search_word| {
+            self.messages_with_word_containing(search_word)
+        });
+    }
+
+    fn search_with(
+        &self,
+        search_term: &str,
+        word_matches: impl Fn(&str) -> Vec<MessageRef>,
+    ) -> Option<Vec<MessageRef>> {
+        let search_words = words(search_term);
+        if search_words.is_empty() {
+            return None;
+        }
+
+        let mut matches: Option<Vec<MessageRef>> = None;
+        for search_word in search_words {
+            let word_matches = word_matches(&search_word);
+            matches = Some(match matches {
+                None => word_matches,
+                Some(matches) => intersect(&matches, &word_matches),
+            });
+            if matches.as_ref().is_some_and(|matches| matches.is_empty()) {
+                break;
+            }
+        }
+        return matches;
+    }
+
+    /// Indexed words close enough to `word` to be a different spelling of it, with how many edits
+    /// away they are and the messages they're in.
+    pub fn similar_words(&self, word: &str) -> Vec<(&str, usize, &[MessageRef])> {
+        let max_edits = max_edits(word);
+        let length = word.chars().count();
+        let mut similar = vec![];
+        for (indexed_word, messages) in &self.words {
+            // Can't be close enough if the lengths are too different
+            if indexed_word.chars().count().abs_diff(length) > max_edits {
+                continue;
+            }
+            let distance = edit_distance(word, indexed_word);
+            if distance <= max_edits {
+                similar.push((indexed_word.as_str(), distance, messages.as_slice()));
+            }
+        }
+        return similar;
+    }
+
+    fn messages_with_prefix(&self, prefix: &str) -> Vec<MessageRef> {
+        let mut messages = vec![];
+        for (_, word_messages) in self
+            .words
+            .range(prefix.to_string()..)
+            .take_while(|(word, _)| word.starts_with(prefix))
+        {
+            messages.extend(word_messages);
+        }
+        // A message can have more than one word with the same start
+        messages.sort();
+        messages.dedup();
+        return messages;
+    }
+
+    /// Only the words with the rarest piece of the search word in them need checking.
+    fn messages_with_word_containing(&self, search_word: &str) -> Vec<MessageRef> {
+        let length = search_word.chars().count().min(GRAM_LENGTH);
+        let rarest_gram_words = grams(search_word, length)
+            .iter()
+            .map(|gram| self.grams.get(gram).map(Vec::as_slice).unwrap_or_default())
+            .min_by_key(|words| words.len())
+            .unwrap_or_default();
+        let mut messages = vec![];
+        for word in rarest_gram_words
+            .iter()
+            .filter(|word| word.contains(search_word))
+        {
+            messages.extend(&self.words[word]);
+        }
+        // A message can have more than one word containing it
+        messages.sort();
+        messages.dedup();
+        return messages;
+    }
+}
+
+/// Every run of `length` characters in a word.
+fn grams(word: &str, length: usize) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    return chars
+        .windows(length)
+        .map(|gram| gram.iter().collect())
+        .collect();
+}
+
+/// Lowercased runs of letters and numbers.
+pub fn words(string: &str) -> Vec<String> {
+    return words_as_written(&string.to_lowercase())
+        .into_iter()
+        .map(|word| word.to_string())
+        .collect();
+}
+
+/// Runs of letters and numbers, keeping their case.
+pub fn words_as_written(string: &str) -> Vec<&str> {
+    return word_ranges(string)
+        .into_iter()
+        .map(|range| &string[range])
+        .collect();
+}
+
+/// Where each run of letters and numbers is, as byte ranges.
+pub fn word_ranges(string: &str) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut word_start = None;
+    for (i, c) in string.char_indices() {
+        match (c.is_alphanumeric(), word_start) {
+            (true, None) => word_start = Some(i),
+            (false, Some(start)) => {
+                ranges.push(start..i);
+                word_start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = word_start {
+        ranges.push(start..string.len());
+    }
+    return ranges;
+}
+
+/// How many typos a word can have and still count as the same word. Short words have to be
+/// spelt right, otherwise everything matches them.
+fn max_edits(word: &str) -> usize {
+    return word.chars().count() / 3;
+}
+
+/// Levenshtein distance, the fewest single character insertions, deletions and substitutions to
+/// turn one word into the other.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous_row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous_row[j] + if a_char == *b_char { 0 } else { 1 };
+            let insertion = row[j] + 1;
+            let deletion = previous_row[j + 1] + 1;
+            row.push(substitution.min(insertion).min(deletion));
+        }
+        previous_row = row;
+    }
+    return previous_row[b.len()];
+}
+
+/// Both lists must be sorted.
+fn intersect(a: &[MessageRef], b: &[MessageRef]) -> Vec<MessageRef> {
+    let mut result = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                result.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ChatType;
+
+    use super::{edit_distance, words, MessageRef, SearchIndex};
+
+    fn message_ref(id: u32) -> MessageRef {
+        return MessageRef {
+            id,
+            chat_type: ChatType::Trade,
+            index: id as usize,
+        };
+    }
+
+    #[test]
+    fn test_words() {
+        assert_eq!(
+            words("[16:05:04] Big Barry trade chats, \"Buying IRON!\""),
+            vec!["16", "05", "04", "big", "barry", "trade", "chats", "buying", "iron"]
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        let mut index = SearchIndex::new();
+        index.insert("Buying iron, weavery on barb", message_ref(1));
+        index.insert("Selling iron", message_ref(2));
+        index.insert("Buying weaverie and irons", message_ref(3));
+
+        assert_eq!(
+            index.search("iron"),
+            Some(vec![message_ref(1), message_ref(2), message_ref(3)])
+        );
+        assert_eq!(
+            index.search("BUYING weav"),
+            Some(vec![message_ref(1), message_ref(3)])
+        );
+        assert_eq!(index.search("selling barb"), Some(vec![]));
+        assert_eq!(index.search("ron"), Some(vec![]));
+        assert_eq!(index.search("?!"), None);
+    }
+
+    #[test]
+    fn test_search_anywhere() {
+        let mut index = SearchIndex::new();
+        index.insert("Buying iron, weavery on barb", message_ref(1));
+        index.insert("Selling iron", message_ref(2));
+        index.insert("Buying weaverie and irons", message_ref(3));
+
+        assert_eq!(
+            index.search_anywhere("ron"),
+            Some(vec![message_ref(1), message_ref(2), message_ref(3)])
+        );
+        assert_eq!(
+            index.search_anywhere("ing ver"),
+            Some(vec![message_ref(1), message_ref(3)])
+        );
+        assert_eq!(index.search_anywhere("ie"), Some(vec![message_ref(3)]));
+        assert_eq!(index.search_anywhere("ironsides"), Some(vec![]));
+        assert_eq!(index.search_anywhere("?!"), None);
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("weavery", "weavery"), 0);
+        assert_eq!(edit_distance("weavery", "weavry"), 1);
+        assert_eq!(edit_distance("weavery", "weaverie"), 2);
+        assert_eq!(edit_distance("", "iron"), 4);
+    }
+
+    #[test]
+    fn test_similar_words() {
+        let mut index = SearchIndex::new();
+        index.insert("weavery weavry weaverie wearing", message_ref(1));
+        index.insert("iron irons icon", message_ref(2));
+
+        let similar: Vec<(&str, usize)> = index
+            .similar_words("weavery")
+            .into_iter()
+            .map(|(word, distance, _)| (word, distance))
+            .collect();
+        assert_eq!(
+            similar,
+            vec![("weaverie", 2), ("weavery", 0), ("weavry", 1)]
+        );
+        // Short words get fewer typos
+        assert_eq!(index.similar_words("iron").len(), 3);
+        assert_eq!(index.similar_words("ir").len(), 0);
+    }
+}
@@ -0,0 +1,765 @@
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use time::macros::format_description;
+use time::{Date, Time};
+
+use crate::chat_log::ParsedChatLog;
+use crate::search_index::words_as_written;
+use crate::{ChatType, Message};
+
+/// What can go in the search box, shown as a hint under it.
+pub const SEARCH_HELP: &str = "Words match the start of words in a message. Use \"quotes\" for an exact phrase, -word or NOT to leave messages out, OR between alternatives and brackets to group. Filters: from:Bob in:trade after:2024-01-01 before:12:00";
+
+/// How what's typed in the search box is read.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// Words, phrases and filters, see SEARCH_HELP
+    #[default]
+    Query,
+    /// The whole search is a regular expression
+    Regex,
+    /// Words spelt roughly the same, best matches first
+    Fuzzy,
+}
+
+/// The toggles next to the search box.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchOptions {
+    pub mode: SearchMode,
+    /// Words only match whole words, rather than the start of them
+    pub whole_word: bool,
+    pub case_sensitive: bool,
+}
+
+/// A message found by a search, with the parts of it to highlight as byte ranges of its contents.
+pub struct SearchResult<'a> {
+    pub message: &'a Message,
+    pub highlights: Vec<Range<usize>>,
+}
+
+/// A search ready to run against the chat log. Building one parses the query or compiles the
+/// regex, so keep it around for as long as the search box doesn't change.
+pub struct Search {
+    term: String,
+    options: SearchOptions,
+    /// Only messages in this chat, unless it's All
+    channel: ChatType,
+    /// Set when searching for mentions of these pirates. Only what was said counts, and their
+    /// own messages never match.
+    mentioned: Vec<String>,
+    compiled: Result<Option<CompiledSearch>, String>,
+}
+
+enum CompiledSearch {
+    Query(Query),
+    Regex(Regex),
+    Fuzzy(String),
+}
+
+impl Search {
+    pub fn new(term: &str, options: SearchOptions) -> Search {
+        let compiled = if options.mode == SearchMode::Query {
+            Query::parse(term).map(|query| query.map(CompiledSearch::Query))
+        } else if term.trim().is_empty() {
+            Ok(None)
+        } else if options.mode == SearchMode::Fuzzy {
+            Ok(Some(CompiledSearch::Fuzzy(term.to_string())))
+        } else {
+            let pattern = if options.whole_word {
+                format!(r"\b(?:{})\b", term)
+            } else {
+                term.to_string()
+            };
+            RegexBuilder::new(&pattern)
+                .case_insensitive(!options.case_sensitive)
+                .build()
+                .map(|regex| Some(CompiledSearch::Regex(regex)))
+                .map_err(|e| e.to_string())
+        };
+        return Search {
+            term: term.to_string(),
+            options,
+            channel: ChatType::All,
+            mentioned: vec![],
+            compiled,
+        };
+    }
+
+    /// Messages naming any of these pirates as a whole word, ignoring case. What they said
+    /// themselves doesn't count.
+    pub fn mentioning(names: &[String]) -> Search {
+        let names: Vec<&str> = names
+            .iter()
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .collect();
+        let pattern: Vec<String> = names.iter().map(|name| regex::escape(name)).collect();
+        let options = SearchOptions {
+            mode: SearchMode::Regex,
+            whole_word: true,
+            case_sensitive: false,
+        };
+        let mut search = Search::new(&pattern.join("|"), options);
+        search.mentioned = names.iter().map(|name| name.to_string()).collect();
+        return search;
+    }
+
+    pub fn in_channel(mut self, channel: ChatType) -> Search {
+        self.channel = channel;
+        return self;
+    }
+
+    /// Whether this was built from the same search box, so doesn't need building again.
+    pub fn is_for(&self, term: &str, options: SearchOptions) -> bool {
+        return self.term == term && self.options == options;
+    }
+
+    /// Why the search couldn't be used, ready to show to the user.
+    pub fn error(&self) -> Option<&str> {
+        return self.compiled.as_ref().err().map(|e| e.as_str());
+    }
+
+    /// Matching messages in the order to show them: newest first, or best first for fuzzy
+    /// searches. Nothing matches an empty or broken search.
+    pub fn results<'a>(&self, parsed: &'a ParsedChatLog) -> Vec<SearchResult<'a>> {
+        let mut results = match &self.compiled {
+            Ok(Some(CompiledSearch::Query(query))) => parsed
+                .messages_matching_query(query, &self.options)
+                .into_iter()
+                .rev()
+                .map(|message| SearchResult {
+                    message,
+                    highlights: vec![],
+                })
+                .collect(),
+            Ok(Some(CompiledSearch::Regex(regex))) => parsed
+                .messages_in_order_of_creation()
+                .into_iter()
+                .rev()
+                .filter(|message| regex.is_match(&message.contents))
+                .map(|message| SearchResult {
+                    message,
+                    highlights: regex
+                        .find_iter(&message.contents)
+                        .map(|found| found.range())
+                        .collect(),
+                })
+                .collect(),
+            Ok(Some(CompiledSearch::Fuzzy(term))) => parsed.messages_fuzzy_matching(term),
+            Ok(None) | Err(_) => vec![],
+        };
+        if self.channel != ChatType::All {
+            results.retain(|result| result.message.chat_type == self.channel);
+        }
+        if !self.mentioned.is_empty() {
+            results.retain_mut(|result| {
+                let message = result.message;
+                if self
+                    .mentioned
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(&message.sender))
+                {
+                    return false;
+                }
+                let said_at = message.sender_indexes().1;
+                result.highlights.retain(|range| range.start >= said_at);
+                return !result.highlights.is_empty();
+            });
+        }
+        return results;
+    }
+}
+
+/// A parsed search. Everything side by side has to match, like a search engine.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// Each word has to start a word in the message
+    Words(String),
+    /// Has to appear in the message exactly as written, ignoring case
+    Phrase(String),
+    From(String),
+    In(ChatType),
+    /// On or after
+    After(Moment),
+    /// Strictly before
+    Before(Moment),
+    Not(Box<Query>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+/// A day, or a time of day on any day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Moment {
+    Date(Date),
+    Time(Time),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Phrase(String),
+    Filter(String, String),
+    Minus,
+    Not,
+    And,
+    Or,
+    OpenBracket,
+    CloseBracket,
+}
+
+impl Query {
+    /// None if there's nothing to search for. Errors are ready to show to the user.
+    pub fn parse(query: &str) -> Result<Option<Query>, String> {
+        let tokens = tokenize(query)?;
+        if tokens.is_empty() {
+            return Ok(None);
+        }
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            depth: 0,
+        };
+        let query = parser.or()?;
+        if parser.peek().is_some() {
+            return Err("\")\" without a matching \"(\"".to_string());
+        }
+        return Ok(Some(query));
+    }
+
+    pub fn matches(&self, message: &Message, options: &SearchOptions) -> bool {
+        return match self {
+            Query::Words(text) => {
+                let (contents, text) = fold_case(&message.contents, text, options);
+                let message_words = words_as_written(&contents);
+                let search_words = words_as_written(&text);
+                if search_words.is_empty() {
+                    // Nothing but punctuation, so look for it as is
+                    return contents.contains(&text);
+                }
+                search_words.iter().all(|search_word| {
+                    message_words.iter().any(|word| {
+                        if options.whole_word {
+                            word == search_word
+                        } else {
+                            word.starts_with(search_word)
+                        }
+                    })
+                })
+            }
+            Query::Phrase(phrase) => {
+                let (contents, phrase) = fold_case(&message.contents, phrase, options);
+                if !options.whole_word {
+                    return contents.contains(&phrase);
+                }
+                contents.match_indices(&phrase).any(|(start, _)| {
+                    let before = contents[..start].chars().next_back();
+                    let after = contents[start + phrase.len()..].chars().next();
+                    !before.is_some_and(|c| c.is_alphanumeric())
+                        && !after.is_some_and(|c| c.is_alphanumeric())
+                })
+            }
+            Query::From(sender) => message.sender.to_lowercase() == sender.to_lowercase(),
+            Query::In(ChatType::All) => true,
+            Query::In(chat_type) => message.chat_type == *chat_type,
+            Query::After(Moment::Date(date)) => message.date.is_some_and(|d| d >= *date),
+            Query::After(Moment::Time(time)) => message.timestamp >= *time,
+            Query::Before(Moment::Date(date)) => message.date.is_some_and(|d| d < *date),
+            Query::Before(Moment::Time(time)) => message.timestamp < *time,
+            Query::Not(query) => !query.matches(message, options),
+            Query::And(queries) => queries.iter().all(|query| query.matches(message, options)),
+            Query::Or(queries) => queries.iter().any(|query| query.matches(message, options)),
+        };
+    }
+
+    /// Words every match has to have, so the search index can narrow things down before checking
+    /// the rest of the query.
+    pub fn required_words(&self) -> Vec<&str> {
+        return match self {
+            Query::Words(search_words) => vec![search_words.as_str()],
+            Query::And(queries) => queries
+                .iter()
+                .flat_map(|query| query.required_words())
+                .collect(),
+            _ => vec![],
+        };
+    }
+}
+
+/// Lowercases the message and what's being looked for, unless the search is case sensitive.
+fn fold_case(contents: &str, text: &str, options: &SearchOptions) -> (String, String) {
+    if options.case_sensitive {
+        return (contents.to_string(), text.to_string());
+    }
+    return (contents.to_lowercase(), text.to_lowercase());
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        match c {
+            '(' => {
+                chars.next();
+                tokens.push(Token::OpenBracket);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::CloseBracket);
+            }
+            '"' => {
+                chars.next();
+                let phrase = read_quoted(&mut chars)?;
+                tokens.push(Token::Phrase(phrase));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+
+                if word == "-" {
+                    if matches!(chars.peek(), Some('"') | Some('(')) {
+                        tokens.push(Token::Minus);
+                        continue;
+                    }
+                    // A - on its own, most likely a typo for -word
+                    return Err("\"-\" needs a word straight after it, like -selling".to_string());
+                }
+                if word.starts_with('-') && can_negate(&word) {
+                    tokens.push(Token::Minus);
+                    word.remove(0);
+                }
+
+                if let Some((name, value)) = filter_parts(&word) {
+                    let mut value = value.to_string();
+                    if value.is_empty() && chars.peek() == Some(&'"') {
+                        chars.next();
+                        value = read_quoted(&mut chars)?;
+                    }
+                    tokens.push(Token::Filter(name.to_lowercase(), value));
+                    continue;
+                }
+
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word(word),
+                });
+            }
+        }
+    }
+    return Ok(tokens);
+}
+
+/// -word leaves out messages with the word, but a - that's part of something else (like -5) is
+/// just searched for.
+fn can_negate(word: &str) -> bool {
+    return word[1..].chars().next().is_some_and(|c| c.is_alphabetic());
+}
+
+/// `from:Bob` gives ("from", "Bob"). Only letters can come before the colon, so times like 12:00
+/// are still searched for as words.
+fn filter_parts(word: &str) -> Option<(&str, &str)> {
+    let (name, value) = word.split_once(':')?;
+    if name.is_empty() || !name.chars().all(|c| c.is_alphabetic()) {
+        return None;
+    }
+    return Some((name, value));
+}
+
+fn read_quoted(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+    let mut quoted = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => break,
+            Some(c) => quoted.push(c),
+            None => return Err(format!("Missing closing quote after \"{}", quoted)),
+        }
+    }
+    if quoted.trim().is_empty() {
+        return Err("Nothing between the quotes".to_string());
+    }
+    return Ok(quoted);
+}
+
+/// Recursive descent, loosest first: OR, then AND (or just side by side), then NOT and brackets.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        return self.tokens.get(self.position);
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        return token;
+    }
+
+    /// Whether there's something to search for next, rather than an operator or the end.
+    fn at_operand(&self) -> bool {
+        return !matches!(
+            self.peek(),
+            None | Some(Token::CloseBracket) | Some(Token::Or) | Some(Token::And)
+        );
+    }
+
+    fn or(&mut self) -> Result<Query, String> {
+        let mut queries = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            if !self.at_operand() {
+                return Err("\"OR\" needs something to search for after it".to_string());
+            }
+            queries.push(self.and()?);
+        }
+        if queries.len() == 1 {
+            return Ok(queries.pop().unwrap());
+        }
+        return Ok(Query::Or(queries));
+    }
+
+    fn and(&mut self) -> Result<Query, String> {
+        let mut queries = vec![];
+        loop {
+            match self.peek() {
+                None | Some(Token::CloseBracket) | Some(Token::Or) => break,
+                Some(Token::And) => {
+                    if queries.is_empty() {
+                        return Err("\"AND\" needs something to search for before it".to_string());
+                    }
+                    self.next();
+                    if !self.at_operand() {
+                        return Err("\"AND\" needs something to search for after it".to_string());
+                    }
+                }
+                _ => queries.push(self.unary()?),
+            }
+        }
+
+        if queries.is_empty() {
+            return Err(match self.peek() {
+                Some(Token::Or) => "\"OR\" needs something to search for before it",
+                Some(Token::CloseBracket) if self.depth > 0 => "Nothing between the brackets",
+                Some(Token::CloseBracket) => "\")\" without a matching \"(\"",
+                _ => "Nothing to search for",
+            }
+            .to_string());
+        }
+        if queries.len() == 1 {
+            return Ok(queries.pop().unwrap());
+        }
+        return Ok(Query::And(queries));
+    }
+
+    fn unary(&mut self) -> Result<Query, String> {
+        return match self.next() {
+            Some(Token::Minus) | Some(Token::Not) => {
+                if !self.at_operand() {
+                    return Err("\"NOT\" needs something to search for after it".to_string());
+                }
+                Ok(Query::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::OpenBracket) => {
+                self.depth += 1;
+                match self.peek() {
+                    None => return Err("Missing \")\"".to_string()),
+                    Some(Token::CloseBracket) => {
+                        return Err("Nothing between the brackets".to_string())
+                    }
+                    _ => {}
+                }
+                let query = self.or()?;
+                if self.next() != Some(Token::CloseBracket) {
+                    return Err("Missing \")\"".to_string());
+                }
+                self.depth -= 1;
+                Ok(query)
+            }
+            Some(Token::Word(word)) => Ok(Query::Words(word)),
+            Some(Token::Phrase(phrase)) => Ok(Query::Phrase(phrase)),
+            Some(Token::Filter(name, value)) => filter(&name, &value),
+            // at_operand and the loop in and() keep everything else out
+            _ => unreachable!(),
+        };
+    }
+}
+
+fn filter(name: &str, value: &str) -> Result<Query, String> {
+    if value.is_empty() && ["from", "in", "after", "before"].contains(&name) {
+        return Err(format!("\"{}:\" needs something after it", name));
+    }
+    return match name {
+        "from" => Ok(Query::From(value.to_string())),
+        "in" => match ChatType::from_key(value) {
+            Some(chat_type) => Ok(Query::In(chat_type)),
+            None => Err(format!(
+                "Unknown channel \"{}\", expected chat, trade, global or tell",
+                value
+            )),
+        },
+        "after" => Ok(Query::After(parse_moment(name, value)?)),
+        "before" => Ok(Query::Before(parse_moment(name, value)?)),
+        _ => Err(format!(
+            "Unknown filter \"{}:\", expected from:, in:, after: or before:",
+            name
+        )),
+    };
+}
+
+/// Dates like 2024-01-06 (or the chat log's 2024/01/06), times like 12:00 or 12:00:30.
+fn parse_moment(name: &str, value: &str) -> Result<Moment, String> {
+    let date_format = format_description!("[year]-[month]-[day]");
+    if let Ok(date) = Date::parse(&value.replace('/', "-"), &date_format) {
+        return Ok(Moment::Date(date));
+    }
+    let time_format = format_description!("[hour]:[minute]");
+    if let Ok(time) = Time::parse(value, &time_format) {
+        return Ok(Moment::Time(time));
+    }
+    let time_format = format_description!("[hour]:[minute]:[second]");
+    if let Ok(time) = Time::parse(value, &time_format) {
+        return Ok(Moment::Time(time));
+    }
+    return Err(format!(
+        "Couldn't read \"{}:{}\", expected a date like 2024-01-01 or a time like 12:00",
+        name, value
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::{date, time};
+
+    use std::io::BufReader;
+
+    use super::{Moment, Query, Search, SearchMode, SearchOptions};
+    use crate::chat_log::ParsedChatLog;
+    use crate::{ChatType, Message};
+
+    fn message(contents: &str, sender: &str, chat_type: ChatType) -> Message {
+        let mut message = Message::new(
+            contents.to_string(),
+            sender.to_string(),
+            time!(16:05:04),
+            1,
+            chat_type,
+        );
+        message.date = Some(date!(2024 - 01 - 06));
+        return message;
+    }
+
+    fn parse(query: &str) -> Query {
+        return Query::parse(query).unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Query::parse("   "), Ok(None));
+        assert_eq!(parse("iron"), Query::Words("iron".to_string()));
+        assert_eq!(
+            parse("from:Bob in:trade \"iron\" -selling after:2024-01-01 before:12:00"),
+            Query::And(vec![
+                Query::From("Bob".to_string()),
+                Query::In(ChatType::Trade),
+                Query::Phrase("iron".to_string()),
+                Query::Not(Box::new(Query::Words("selling".to_string()))),
+                Query::After(Moment::Date(date!(2024 - 01 - 01))),
+                Query::Before(Moment::Time(time!(12:00))),
+            ])
+        );
+        assert_eq!(
+            parse("iron OR wood AND NOT (spades)"),
+            Query::Or(vec![
+                Query::Words("iron".to_string()),
+                Query::And(vec![
+                    Query::Words("wood".to_string()),
+                    Query::Not(Box::new(Query::Words("spades".to_string()))),
+                ]),
+            ])
+        );
+        assert_eq!(
+            parse("from:\"Big Barry\" 12:00 -5"),
+            Query::And(vec![
+                Query::From("Big Barry".to_string()),
+                Query::Words("12:00".to_string()),
+                Query::Words("-5".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |query: &str| Query::parse(query).unwrap_err();
+        assert_eq!(error("\"iron"), "Missing closing quote after \"iron");
+        assert_eq!(error("(iron"), "Missing \")\"");
+        assert_eq!(error("iron ("), "Missing \")\"");
+        assert_eq!(error("iron)"), "\")\" without a matching \"(\"");
+        assert_eq!(error("()"), "Nothing between the brackets");
+        assert_eq!(
+            error("iron OR"),
+            "\"OR\" needs something to search for after it"
+        );
+        assert_eq!(
+            error("OR iron"),
+            "\"OR\" needs something to search for before it"
+        );
+        assert_eq!(
+            error("iron AND"),
+            "\"AND\" needs something to search for after it"
+        );
+        assert_eq!(
+            error("NOT"),
+            "\"NOT\" needs something to search for after it"
+        );
+        assert_eq!(
+            error("iron - wood"),
+            "\"-\" needs a word straight after it, like -selling"
+        );
+        assert_eq!(error("from:"), "\"from:\" needs something after it");
+        assert_eq!(
+            error("in:docks"),
+            "Unknown channel \"docks\", expected chat, trade, global or tell"
+        );
+        assert_eq!(
+            error("after:soon"),
+            "Couldn't read \"after:soon\", expected a date like 2024-01-01 or a time like 12:00"
+        );
+        assert_eq!(
+            error("to:Bob"),
+            "Unknown filter \"to:\", expected from:, in:, after: or before:"
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        let buying = message(
+            "[16:05:04] Bob trade chats, \"Buying iron and wood\"",
+            "Bob",
+            ChatType::Trade,
+        );
+        let selling = message(
+            "[16:05:04] Bob trade chats, \"Selling iron\"",
+            "Bob",
+            ChatType::Trade,
+        );
+
+        let options = SearchOptions::default();
+        assert!(parse("from:bob in:trade \"iron and\" -selling").matches(&buying, &options));
+        assert!(!parse("from:bob in:trade \"iron and\" -selling").matches(&selling, &options));
+        assert!(parse("buy OR sell").matches(&selling, &options));
+        assert!(!parse("from:Barry").matches(&buying, &options));
+        assert!(!parse("in:global").matches(&buying, &options));
+        assert!(parse("after:2024/01/06 before:16:06").matches(&buying, &options));
+        assert!(!parse("after:16:06").matches(&buying, &options));
+        assert!(!parse("before:2024-01-06").matches(&buying, &options));
+        assert!(!parse("ron").matches(&buying, &options));
+        assert!(parse("\"ron\"").matches(&buying, &options));
+        assert!(!parse("-(sell OR \"and wood\")").matches(&buying, &options));
+    }
+
+    #[test]
+    fn test_matches_with_options() {
+        let message = message(
+            "[16:05:04] Bob trade chats, \"Buying Iron for PoE\"",
+            "Bob",
+            ChatType::Trade,
+        );
+        let whole_word = SearchOptions {
+            whole_word: true,
+            ..Default::default()
+        };
+        assert!(parse("iron").matches(&message, &whole_word));
+        assert!(!parse("iro").matches(&message, &whole_word));
+        assert!(parse("\"iron for\"").matches(&message, &whole_word));
+        assert!(!parse("\"ron for\"").matches(&message, &whole_word));
+
+        let case_sensitive = SearchOptions {
+            case_sensitive: true,
+            ..Default::default()
+        };
+        assert!(parse("Iron PoE").matches(&message, &case_sensitive));
+        assert!(!parse("iron").matches(&message, &case_sensitive));
+        assert!(!parse("\"buying\"").matches(&message, &case_sensitive));
+    }
+
+    #[test]
+    fn test_regex_search() {
+        let log = "[16:05:04] Bob trade chats, \"Buying weavery\"
+[16:05:05] Bob trade chats, \"Selling weavry\"
+[16:05:06] Bob trade chats, \"Weaverie deed\"";
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+
+        let mut options = SearchOptions {
+            mode: SearchMode::Regex,
+            ..Default::default()
+        };
+        let search = Search::new("weave?r(y|ie)", options);
+        assert_eq!(search.error(), None);
+        let results = search.results(&parsed);
+        assert_eq!(results.len(), 3);
+        // Newest first
+        assert_eq!(
+            results[0].message.contents,
+            "[16:05:06] Bob trade chats, \"Weaverie deed\""
+        );
+        assert_eq!(results[0].highlights, vec![29..37]);
+        assert!(search.is_for("weave?r(y|ie)", options));
+
+        options.case_sensitive = true;
+        assert!(!search.is_for("weave?r(y|ie)", options));
+        assert_eq!(
+            Search::new("weave?r(y|ie)", options).results(&parsed).len(),
+            2
+        );
+
+        let trade_search = Search::new("weave?r(y|ie)", options).in_channel(ChatType::Global);
+        assert!(trade_search.results(&parsed).is_empty());
+
+        options.whole_word = true;
+        assert_eq!(Search::new("weav", options).results(&parsed).len(), 0);
+
+        let search = Search::new("weave(ry", options);
+        assert!(search.error().unwrap().contains("unclosed group"));
+        assert!(search.results(&parsed).is_empty());
+    }
+
+    #[test]
+    fn test_mentions() {
+        let log = "[16:05:04] Fred says, \"Ahoy bob, need a hand\"
+[16:05:05] Bob says, \"I'm Bob\"
+[16:05:06] Fred global chats, \"Anyone seen Bobby or Sal.ly?\"
+[16:05:07] Jane tells ye, \"Sal.ly, you there?\"
+[16:05:08] Bob Smith says, \"Arr\"";
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+
+        let names = vec!["Bob".to_string(), " Sal.ly ".to_string(), "".to_string()];
+        let results = Search::mentioning(&names).results(&parsed);
+        let ids: Vec<u32> = results.iter().map(|result| result.message.id).collect();
+        // Whole names only in what was said, not Bob's own message, and the dot isn't a wildcard
+        assert_eq!(ids, vec![4, 3, 1]);
+        assert_eq!(results[2].highlights, vec![28..31]);
+
+        assert!(Search::mentioning(&[]).results(&parsed).is_empty());
+    }
+}
@@ -0,0 +1,596 @@
+use std::io::Stdout;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use crossterm::ExecutableCommand;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph, Tabs as TabsWidget, Wrap};
+use ratatui::{Frame, Terminal};
+
+use crate::alerts::{AlertEngine, SystemAlertSink};
+use crate::chat_log::ParsedChatLog;
+use crate::config_file::load_config;
+use crate::mutes::Mutes;
+use crate::npcs::Npcs;
+use crate::parser_worker::{check_chat_log, ParserCommand, ParserWorker};
+use crate::search_query::{Query, Search, SearchOptions};
+use crate::{ChatType, Config, Tabs, TABS};
+
+const NPC_NAME_COLOR: Color = Color::Rgb(0xFF, 0x45, 0x00);
+const PAGE_SCROLL_LINES: u16 = 10;
+
+/// Terminal frontend state. Shares the parsed chat log and tabs with the window, settings are
+/// left to the window.
+pub struct TuiApp {
+    selected_tab: Tabs,
+    search_term: String,
+    editing_search: bool,
+    scroll: u16,
+    should_quit: bool,
+    mentions: Search,
+    mutes: Mutes,
+    show_muted: bool,
+    npcs: Npcs,
+    /// Chat tabs that leave out NPC messages
+    hide_npc_messages: Vec<ChatType>,
+    shown: ShownMessages,
+}
+
+/// What the selected tab lists, kept until the tab, search or chat log changes.
+#[derive(Default)]
+struct ShownMessages {
+    /// The tab, search term, whether muted messages were shown and the snapshot
+    built_for: Option<(Tabs, String, bool, u64)>,
+    /// Oldest first
+    ids: Vec<u32>,
+    /// Whether they're shown or not
+    muted: usize,
+}
+
+impl TuiApp {
+    pub fn new(config: &Config) -> Self {
+        return TuiApp {
+            selected_tab: Tabs::Chat(ChatType::All),
+            search_term: String::new(),
+            editing_search: false,
+            scroll: 0,
+            should_quit: false,
+            mentions: Search::mentioning(&config.pirate_names),
+            mutes: Mutes::new(&config.ignored_pirates, &config.mute_rules),
+            show_muted: false,
+            npcs: Npcs::new(&config.known_npcs),
+            hide_npc_messages: config.hide_npc_messages.clone(),
+            shown: ShownMessages::default(),
+        };
+    }
+
+    fn tabs() -> Vec<Tabs> {
+        return TABS
+            .into_iter()
+            .filter(|tab| *tab != Tabs::Settings)
+            .collect();
+    }
+
+    fn select_tab(&mut self, tab: Tabs) {
+        if self.selected_tab != tab {
+            self.selected_tab = tab;
+            self.scroll = 0;
+        }
+    }
+
+    fn select_relative_tab(&mut self, offset: isize) {
+        let tabs = TuiApp::tabs();
+        let current = tabs
+            .iter()
+            .position(|tab| *tab == self.selected_tab)
+            .unwrap_or(0);
+        let next = (current as isize + offset).rem_euclid(tabs.len() as isize);
+        self.select_tab(tabs[next as usize]);
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.should_quit = true;
+            return;
+        }
+
+        if self.editing_search {
+            match key.code {
+                KeyCode::Char(c) => self.search_term.push(c),
+                KeyCode::Backspace => {
+                    self.search_term.pop();
+                }
+                KeyCode::Enter | KeyCode::Esc => self.editing_search = false,
+                _ => {}
+            }
+            self.scroll = 0;
+            return;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => self.should_quit = true,
+            KeyCode::Right | KeyCode::Tab | KeyCode::Char('l') => self.select_relative_tab(1),
+            KeyCode::Left | KeyCode::BackTab | KeyCode::Char('h') => self.select_relative_tab(-1),
+            KeyCode::Char(c @ '1'..='9') => {
+                let index = c.to_digit(10).unwrap() as usize - 1;
+                if let Some(tab) = TuiApp::tabs().get(index) {
+                    self.select_tab(*tab);
+                }
+            }
+            KeyCode::Char('m') => self.show_muted = !self.show_muted,
+            KeyCode::Char('/') => {
+                self.select_tab(Tabs::SearchChat);
+                self.editing_search = true;
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.scroll = self.scroll.saturating_add(1),
+            KeyCode::Up | KeyCode::Char('k') => self.scroll = self.scroll.saturating_sub(1),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(PAGE_SCROLL_LINES),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(PAGE_SCROLL_LINES),
+            KeyCode::Home | KeyCode::Char('g') => self.scroll = 0,
+            _ => {}
+        }
+    }
+
+    /// Works out what the selected tab lists again if anything's changed. `snapshot` goes up
+    /// whenever the chat log does.
+    fn update_shown(&mut self, parsed_stuff: &ParsedChatLog, snapshot: u64) -> Result<(), String> {
+        let built_for = Some((
+            self.selected_tab,
+            self.search_term.clone(),
+            self.show_muted,
+            snapshot,
+        ));
+        if self.shown.built_for == built_for {
+            return Ok(());
+        }
+        let messages = match self.selected_tab {
+            Tabs::Chat(chat_type) => {
+                let mut messages = parsed_stuff.messages_of_type(chat_type);
+                if self.hide_npc_messages.contains(&chat_type) {
+                    messages.retain(|message| !message.is_sender_npc(&self.npcs));
+                }
+                messages
+            }
+            // Oldest first, like the other chats
+            Tabs::Mentions => self
+                .mentions
+                .results(parsed_stuff)
+                .iter()
+                .rev()
+                .map(|result| result.message)
+                .collect(),
+            Tabs::SearchChat => match Query::parse(&self.search_term)? {
+                Some(query) => {
+                    parsed_stuff.messages_matching_query(&query, &SearchOptions::default())
+                }
+                None => vec![],
+            },
+            _ => vec![],
+        };
+        self.shown.built_for = built_for;
+        self.shown.ids.clear();
+        self.shown.muted = 0;
+        for message in messages {
+            if self.mutes.is_muted(message) {
+                self.shown.muted += 1;
+                if !self.show_muted {
+                    continue;
+                }
+            }
+            self.shown.ids.push(message.id);
+        }
+        return Ok(());
+    }
+
+    pub fn draw(
+        &mut self,
+        frame: &mut Frame,
+        parsed_stuff: &ParsedChatLog,
+        snapshot: u64,
+        message_limit: usize,
+    ) {
+        let [tabs_area, main_area, help_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.size());
+
+        let tabs = TuiApp::tabs();
+        let titles: Vec<String> = tabs
+            .iter()
+            .enumerate()
+            .map(|(i, tab)| format!("{} {}", i + 1, tab.label()))
+            .collect();
+        let selected = tabs.iter().position(|tab| *tab == self.selected_tab);
+        frame.render_widget(
+            TabsWidget::new(titles)
+                .block(Block::default().borders(Borders::ALL))
+                .select(selected.unwrap_or(0))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+            tabs_area,
+        );
+
+        frame.render_widget(
+            Paragraph::new(
+                "q quit  ←/→ switch tab  1-8 jump to tab  ↑/↓ PgUp/PgDn scroll  / search  m show muted",
+            )
+            .style(Style::default().add_modifier(Modifier::DIM)),
+            help_area,
+        );
+
+        let shown = self.update_shown(parsed_stuff, snapshot);
+        match self.selected_tab {
+            Tabs::Chat(_) | Tabs::Mentions => {
+                self.draw_messages(
+                    frame,
+                    main_area,
+                    self.selected_tab.label(),
+                    parsed_stuff,
+                    message_limit,
+                );
+            }
+            Tabs::SearchChat => {
+                let [search_area, results_area] =
+                    Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).areas(main_area);
+                let search_title = if self.editing_search {
+                    "Search term (Enter to finish)"
+                } else {
+                    "Search term (/ to edit)"
+                };
+                let mut search_style = Style::default();
+                if self.editing_search {
+                    search_style = search_style.fg(Color::Yellow);
+                }
+                frame.render_widget(
+                    Paragraph::new(self.search_term.as_str())
+                        .style(search_style)
+                        .block(Block::default().borders(Borders::ALL).title(search_title)),
+                    search_area,
+                );
+                if let Err(e) = shown {
+                    let error_block = Block::default()
+                        .borders(Borders::ALL)
+                        .title("Filtered chat");
+                    frame.render_widget(
+                        Paragraph::new(e)
+                            .style(Style::default().fg(Color::Red))
+                            .block(error_block),
+                        results_area,
+                    );
+                    return;
+                }
+                self.draw_messages(
+                    frame,
+                    results_area,
+                    "Filtered chat",
+                    parsed_stuff,
+                    message_limit,
+                );
+            }
+            Tabs::GreedyHits => self.draw_greedies(frame, main_area, parsed_stuff),
+            // Saved searches are managed in the window
+            Tabs::SavedSearch(_) | Tabs::Settings => {}
+        }
+    }
+
+    fn draw_messages(
+        &self,
+        frame: &mut Frame,
+        area: ratatui::layout::Rect,
+        title: &str,
+        parsed_stuff: &ParsedChatLog,
+        message_limit: usize,
+    ) {
+        let mut lines: Vec<Line> = vec![];
+        let shown = self
+            .shown
+            .ids
+            .iter()
+            .rev()
+            .take(message_limit)
+            .filter_map(|id| parsed_stuff.message_with_id(*id));
+        for message in shown {
+            let sender_style = if message.is_sender_npc(&self.npcs) {
+                Style::default().fg(NPC_NAME_COLOR)
+            } else {
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD)
+            };
+            lines.push(Line::from(vec![
+                Span::styled(
+                    message.timestamp_from_message().to_string(),
+                    Style::default().add_modifier(Modifier::DIM),
+                ),
+                Span::raw(" "),
+                Span::styled(message.sender.clone(), sender_style),
+                Span::raw(message.contents_without_sender()),
+            ]));
+        }
+        if lines.is_empty() {
+            lines.push(Line::from("No chat messages found."));
+        }
+
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(Block::default().borders(Borders::ALL).title(muted_title(
+                    title,
+                    self.shown.muted,
+                    self.show_muted,
+                )))
+                .wrap(Wrap { trim: false })
+                .scroll((self.scroll, 0)),
+            area,
+        );
+    }
+
+    fn draw_greedies(
+        &self,
+        frame: &mut Frame,
+        area: ratatui::layout::Rect,
+        parsed_stuff: &ParsedChatLog,
+    ) {
+        let mut lines: Vec<Line> = vec![];
+        if parsed_stuff.battles.is_empty() {
+            lines.push(Line::from("No battles detected."));
+        }
+        for battle in &parsed_stuff.battles {
+            lines.push(Line::styled(
+                format!(
+                    "Battle between {} and {}",
+                    battle.attacker_ship, battle.defender_ship
+                ),
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+            let greedy_count: u32 = battle.greedies.values().sum();
+            lines.push(Line::from(format!("{} Greedies in total", greedy_count)));
+            if battle.greedies.is_empty() {
+                lines.push(Line::from("No Greedies for this battle"));
+            }
+            let mut sorted_results: Vec<(&String, &u32)> = battle.greedies.iter().collect();
+            sorted_results.sort_by(|a, b| b.1.cmp(a.1));
+            for entry in sorted_results {
+                lines.push(Line::from(format!("{} got {}", entry.0, entry.1)));
+            }
+            lines.push(Line::from(""));
+        }
+
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(Block::default().borders(Borders::ALL).title("Greedy hits"))
+                .scroll((self.scroll, 0)),
+            area,
+        );
+    }
+}
+
+/// Runs the terminal frontend until the user quits. Uses the chat log from the config unless one
+/// is given.
+pub fn run(chat_log_path: Option<PathBuf>, config_path: &Path) -> Result<(), String> {
+    let mut config = load_config(config_path)?;
+    if chat_log_path.is_some() {
+        config.chat_log_path = chat_log_path;
+    }
+    let chat_log_path = match &config.chat_log_path {
+        Some(path) => path.clone(),
+        None => {
+            return Err(
+                "No chat log given. Pass --log, or pick one with 'Open chat log' in the window"
+                    .to_string(),
+            )
+        }
+    };
+    check_chat_log(&chat_log_path)?;
+
+    let message_limit = config.message_limit.0 as usize;
+    let app = TuiApp::new(&config);
+    // The draw loop below redraws on a timer, so there's nothing to wake up after a reparse.
+    // Alerts that need the window are left to it
+    let mut alert_engine = AlertEngine::new();
+    let alert_rules = config.alert_rules.clone();
+    let mut parser = ParserWorker::spawn(
+        config.poll_interval_ms.duration(),
+        move |parsed, opened| {
+            if opened {
+                alert_engine.reset();
+            }
+            alert_engine.check(parsed, &alert_rules, &mut [&mut SystemAlertSink]);
+        },
+        || {},
+    );
+    if let Some(open) = ParserCommand::open(&config) {
+        parser.send(open);
+    }
+
+    let mut terminal = setup_terminal().map_err(|e| format!("Couldn't set up terminal: {}", e))?;
+    let result = run_app(&mut terminal, app, &mut parser, message_limit);
+    restore_terminal(&mut terminal).map_err(|e| format!("Couldn't restore terminal: {}", e))?;
+    return result.map_err(|e| format!("Terminal error: {}", e));
+}
+
+fn run_app(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    mut app: TuiApp,
+    parser: &mut ParserWorker,
+    message_limit: usize,
+) -> std::io::Result<()> {
+    let mut parsed_stuff = ParsedChatLog::new();
+    let mut snapshot = 0;
+    while !app.should_quit {
+        if parser.receive(&mut parsed_stuff) {
+            snapshot += 1;
+        }
+        terminal.draw(|frame| app.draw(frame, &parsed_stuff, snapshot, message_limit))?;
+
+        // Waking up regularly picks up anything the parser has found
+        if event::poll(Duration::from_millis(250))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    app.handle_key(key);
+                }
+            }
+        }
+    }
+    return Ok(());
+}
+
+/// A list's title, saying how many messages the ignore list and mute rules hid.
+fn muted_title(title: &str, muted: usize, show_muted: bool) -> String {
+    if muted == 0 {
+        return title.to_string();
+    }
+    if show_muted {
+        return format!("{} ({} muted, m to hide)", title, muted);
+    }
+    return format!("{} ({} hidden, m to show)", title, muted);
+}
+
+fn setup_terminal() -> std::io::Result<Terminal<CrosstermBackend<Stdout>>> {
+    enable_raw_mode()?;
+    std::io::stdout().execute(EnterAlternateScreen)?;
+    // Leave the user's terminal usable if we panic
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = disable_raw_mode();
+        let _ = std::io::stdout().execute(LeaveAlternateScreen);
+        default_hook(info);
+    }));
+    return Terminal::new(CrosstermBackend::new(std::io::stdout()));
+}
+
+fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> std::io::Result<()> {
+    disable_raw_mode()?;
+    terminal.backend_mut().execute(LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    use crate::chat_log::ParsedChatLog;
+    use crate::{ChatType, Config, Tabs};
+
+    use super::TuiApp;
+
+    fn parsed_log() -> ParsedChatLog {
+        let log = "[16:05:01] Someone says, \"we just got intercepted\"
+[16:05:04] Big Barry trade chats, \"Buying weavery\"
+[16:05:05] Bob tells ye, \"2 for spades\"
+[02:01:19] Mean Shad has grappled Shifty Shiner. A melee breaks out between the crews!
+[02:01:54] Bob delivers an overwhelming barrage against Petty Robert, causing some treasure to fall from their grip";
+        let mut parsed = ParsedChatLog::new();
+        parsed.parse_chat_log(BufReader::new(log.as_bytes()));
+        return parsed;
+    }
+
+    fn render(app: &mut TuiApp, parsed: &ParsedChatLog) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        terminal
+            .draw(|frame| app.draw(frame, parsed, 0, 1000))
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        let mut screen = String::new();
+        for y in 0..buffer.area.height {
+            for x in 0..buffer.area.width {
+                screen.push_str(buffer.get(x, y).symbol());
+            }
+            screen.push('\n');
+        }
+        return screen;
+    }
+
+    fn press(app: &mut TuiApp, code: KeyCode) {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    #[test]
+    fn test_all_chat_tab() {
+        let mut app = TuiApp::new(&Config::default());
+        let screen = render(&mut app, &parsed_log());
+        assert!(screen.contains("1 All chat"));
+        assert!(screen.contains("[16:05:01] Someone says, \"we just got intercepted\""));
+        assert!(screen.contains("[16:05:04] Big Barry trade chats, \"Buying weavery\""));
+    }
+
+    #[test]
+    fn test_muted_messages() {
+        let config = Config {
+            ignored_pirates: vec!["Big Barry".to_string()],
+            ..Default::default()
+        };
+        let mut app = TuiApp::new(&config);
+        let screen = render(&mut app, &parsed_log());
+        assert!(screen.contains("All chat (1 hidden, m to show)"));
+        assert!(!screen.contains("Buying weavery"));
+
+        press(&mut app, KeyCode::Char('m'));
+        let screen = render(&mut app, &parsed_log());
+        assert!(screen.contains("All chat (1 muted, m to hide)"));
+        assert!(screen.contains("Buying weavery"));
+    }
+
+    #[test]
+    fn test_switching_tabs() {
+        let parsed = parsed_log();
+        let mut app = TuiApp::new(&Config::default());
+        press(&mut app, KeyCode::Right);
+        press(&mut app, KeyCode::Right);
+        assert!(app.selected_tab == Tabs::Chat(ChatType::Trade));
+        let screen = render(&mut app, &parsed);
+        assert!(screen.contains("Buying weavery"));
+        assert!(!screen.contains("we just got intercepted"));
+
+        press(&mut app, KeyCode::Left);
+        press(&mut app, KeyCode::Left);
+        press(&mut app, KeyCode::Left);
+        assert!(app.selected_tab == Tabs::GreedyHits);
+        let screen = render(&mut app, &parsed);
+        assert!(screen.contains("Battle between Mean Shad and Shifty Shiner"));
+        assert!(screen.contains("Bob got 1"));
+
+        press(&mut app, KeyCode::Char('5'));
+        assert!(app.selected_tab == Tabs::Chat(ChatType::Tell));
+    }
+
+    #[test]
+    fn test_search() {
+        let parsed = parsed_log();
+        let mut app = TuiApp::new(&Config::default());
+        press(&mut app, KeyCode::Char('/'));
+        assert!(app.selected_tab == Tabs::SearchChat);
+        for c in "spadez".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        press(&mut app, KeyCode::Backspace);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.search_term, "spade");
+
+        let screen = render(&mut app, &parsed);
+        assert!(screen.contains("2 for spades"));
+        assert!(!screen.contains("Buying weavery"));
+
+        press(&mut app, KeyCode::Char('/'));
+        press(&mut app, KeyCode::Char('('));
+        press(&mut app, KeyCode::Enter);
+        let screen = render(&mut app, &parsed);
+        assert!(screen.contains("Missing \")\""));
+
+        // Out of the search box, q quits again
+        press(&mut app, KeyCode::Char('q'));
+        assert!(app.should_quit);
+    }
+}